@@ -1,5 +1,6 @@
 use aurora_engine_types::types::Address;
 use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{U128, U64};
 use near_sdk::serde::{Deserialize, Serialize};
 use near_sdk::{
     env, ext_contract, near_bindgen, AccountId, Gas, NearToken, PanicOnDefault, Promise,
@@ -8,10 +9,32 @@ use near_sdk::{
 const FORWARDER_WASM: &[u8] = include_bytes!("../../res/aurora-forwarder.wasm");
 const STORAGE_BALANCE_BOUND: NearToken = NearToken::from_yoctonear(1_250_000_000_000_000_000_000);
 const FORWARDER_NEW_GAS: Gas = Gas::from_tgas(2);
-const FORWARD_TOKENS_GAS: Gas = Gas::from_tgas(150);
+const UPGRADE_FORWARDER_GAS: Gas = Gas::from_tgas(50);
 
 pub const MAX_NUM_CONTRACTS: usize = 12;
-pub const INIT_BALANCE: NearToken = NearToken::from_millinear(310);
+/// Balance which covers the storage staking of the forwarder.
+///
+/// It's 1.25 NEAR instead of 0.31 NEAR of the first release, because the code of the forwarder
+/// grew from about 36 KB to about 117 KB. Every forwarder created by the factory locks this
+/// balance.
+pub const INIT_BALANCE: NearToken = NearToken::from_millinear(1_250);
+
+// The balance is raised explicitly rather than derived from the size of the code, so the build
+// fails once the code doesn't fit the balance anymore.
+const _: () = {
+    /// Number of bytes used by the forwarder account besides its code: the account itself,
+    /// access keys and the state.
+    const FORWARDER_STORAGE_OVERHEAD: u128 = 2_000;
+    const STORAGE_PRICE_PER_BYTE: u128 = 10_000_000_000_000_000_000;
+
+    #[allow(clippy::as_conversions)]
+    let staking =
+        (FORWARDER_WASM.len() as u128 + FORWARDER_STORAGE_OVERHEAD) * STORAGE_PRICE_PER_BYTE;
+    assert!(
+        staking <= INIT_BALANCE.as_yoctonear(),
+        "The initial balance doesn't cover the storage staking of the forwarder"
+    );
+};
 
 #[near_bindgen]
 #[derive(BorshDeserialize, BorshSerialize, PanicOnDefault)]
@@ -54,18 +77,24 @@ impl AuroraForwarderFactory {
         parameters
             .into_iter()
             .map(|params| {
-                let forwarder_id = create_forwarder_id(
-                    &params.target_address,
-                    &params.target_network,
-                    &self.fees_contract_id,
-                );
+                // The forwarder which sends the tokens to the NEAR account is identified by the
+                // account as the network with the zero address.
+                let (target_address, target_network) = match &params.target_account_id {
+                    Some(account_id) => (Address::zero().encode(), account_id),
+                    None => (params.target_address.clone(), &params.target_network),
+                };
+                let forwarder_id =
+                    create_forwarder_id(&target_address, target_network, &self.fees_contract_id);
                 let args = borsh::to_vec(&ForwarderParameters {
-                    target_address: Address::decode(params.target_address.trim_start_matches("0x"))
+                    target_address: Address::decode(target_address.trim_start_matches("0x"))
                         .unwrap(),
-                    target_network: &params.target_network,
+                    target_network,
                     wnear_contract_id: &params.wnear_contract_id,
                     fees_contract_id: &self.fees_contract_id,
                     owner_id: &env::current_account_id(),
+                    add_full_access_key: !params.skip_full_access_key,
+                    target_account_id: params.target_account_id.as_ref(),
+                    max_fee_bps: params.max_fee_bps,
                 })
                 .expect("Couldn't create args");
 
@@ -90,13 +119,232 @@ impl AuroraForwarderFactory {
             .collect::<Vec<_>>()
     }
 
-    /// Forward tokens for a specific forwarder.
+    /// Forward tokens for a specific forwarder. The whole balance is forwarded if the amount
+    /// is omitted. The result is the JSON receipt of the forwarding returned by the forwarder.
+    ///
+    /// All the remaining gas is attached to the call, because the gas of the forwarding grows
+    /// with the number of the targets.
+    #[private]
+    pub fn forward_tokens(
+        &mut self,
+        forwarder_id: AccountId,
+        token_id: AccountId,
+        amount: Option<U128>,
+    ) -> Promise {
+        ext_forwarder::ext(forwarder_id)
+            .with_attached_deposit(NearToken::from_yoctonear(1))
+            .forward(token_id, amount.map(|a| a.0))
+    }
+
+    /// Forward the whole balances of several tokens for a specific forwarder. All the remaining
+    /// gas is attached to the call. The forwardings of the tokens proceed on their own, so the
+    /// call doesn't wait for their results.
     #[private]
-    pub fn forward_tokens(&mut self, forwarder_id: AccountId, token_id: AccountId) -> Promise {
+    pub fn forward_many_tokens(
+        &mut self,
+        forwarder_id: AccountId,
+        token_ids: Vec<AccountId>,
+    ) -> Promise {
         ext_forwarder::ext(forwarder_id)
-            .with_static_gas(FORWARD_TOKENS_GAS)
             .with_attached_deposit(NearToken::from_yoctonear(1))
-            .forward(token_id)
+            .forward_many(token_ids)
+    }
+
+    /// Set new target address for a specific forwarder.
+    ///
+    /// # Panics
+    ///
+    /// The method panics if the target address is wrong.
+    #[private]
+    #[allow(clippy::needless_pass_by_value)]
+    pub fn set_forwarder_target(
+        &mut self,
+        forwarder_id: AccountId,
+        target_address: String,
+    ) -> Promise {
+        let target_address =
+            Address::decode(target_address.trim_start_matches("0x")).expect("Wrong target address");
+        ext_forwarder::ext(forwarder_id).set_target(target_address)
+    }
+
+    /// Split the forwarded tokens of a specific forwarder between several addresses
+    /// proportionally to their weights.
+    ///
+    /// # Panics
+    ///
+    /// The method panics if any of the target addresses is wrong.
+    #[private]
+    pub fn set_forwarder_targets(
+        &mut self,
+        forwarder_id: AccountId,
+        targets: Vec<(String, u32)>,
+    ) -> Promise {
+        let targets = targets
+            .into_iter()
+            .map(|(address, weight)| {
+                let address = Address::decode(address.trim_start_matches("0x"))
+                    .expect("Wrong target address");
+                (address, weight)
+            })
+            .collect();
+        ext_forwarder::ext(forwarder_id).set_targets(targets)
+    }
+
+    /// Set new target network for a specific forwarder.
+    #[private]
+    pub fn set_forwarder_target_network(
+        &mut self,
+        forwarder_id: AccountId,
+        target_network: AccountId,
+    ) -> Promise {
+        ext_forwarder::ext(forwarder_id).set_target_network(target_network)
+    }
+
+    /// Set new NEAR account which a specific forwarder sends the tokens to. The forwarder must
+    /// have been created with the NEAR account.
+    #[private]
+    pub fn set_forwarder_target_account(
+        &mut self,
+        forwarder_id: AccountId,
+        target_account_id: AccountId,
+    ) -> Promise {
+        ext_forwarder::ext(forwarder_id).set_target_account(target_account_id)
+    }
+
+    /// Set the format of the `msg` which a specific forwarder passes with the tokens to the
+    /// target network.
+    #[private]
+    pub fn set_forwarder_msg_format(
+        &mut self,
+        forwarder_id: AccountId,
+        msg_format: MsgFormat,
+    ) -> Promise {
+        ext_forwarder::ext(forwarder_id).set_msg_format(msg_format)
+    }
+
+    /// Set the balance in yoctoNEAR which a specific forwarder keeps on top of its storage
+    /// staking when native NEAR is forwarded.
+    #[private]
+    pub fn set_forwarder_reserve_margin(
+        &mut self,
+        forwarder_id: AccountId,
+        reserve_margin: U128,
+    ) -> Promise {
+        ext_forwarder::ext(forwarder_id).set_reserve_margin(reserve_margin.0)
+    }
+
+    /// Release the lock of the token left by an unfinished forwarding of a specific forwarder.
+    #[private]
+    pub fn unlock_forwarder_token(
+        &mut self,
+        forwarder_id: AccountId,
+        token_id: AccountId,
+    ) -> Promise {
+        ext_forwarder::ext(forwarder_id).unlock(token_id)
+    }
+
+    /// Override the minimum gas of every transfer of the token to the targets of a specific
+    /// forwarder. The default gas is used again if the gas is omitted.
+    #[private]
+    pub fn set_forwarder_transfer_gas(
+        &mut self,
+        forwarder_id: AccountId,
+        token_id: AccountId,
+        gas: Option<U64>,
+    ) -> Promise {
+        ext_forwarder::ext(forwarder_id).set_transfer_gas(token_id, gas.map(|gas| gas.0))
+    }
+
+    /// Set the accounts which could start the forwarding of a specific forwarder. The factory
+    /// as the owner could always start it.
+    #[private]
+    pub fn set_forwarder_caller_policy(
+        &mut self,
+        forwarder_id: AccountId,
+        caller_policy: CallerPolicy,
+    ) -> Promise {
+        ext_forwarder::ext(forwarder_id).set_caller_policy(caller_policy)
+    }
+
+    /// Add the relayer which could start the forwarding of a specific forwarder with the
+    /// `relayers` caller policy.
+    #[private]
+    pub fn add_forwarder_relayer(
+        &mut self,
+        forwarder_id: AccountId,
+        relayer_id: AccountId,
+    ) -> Promise {
+        ext_forwarder::ext(forwarder_id).add_relayer(relayer_id)
+    }
+
+    /// Remove the relayer of a specific forwarder.
+    #[private]
+    pub fn remove_forwarder_relayer(
+        &mut self,
+        forwarder_id: AccountId,
+        relayer_id: AccountId,
+    ) -> Promise {
+        ext_forwarder::ext(forwarder_id).remove_relayer(relayer_id)
+    }
+
+    /// Set the number of blocks a specific forwarder caches the fee rates of the fees contract
+    /// for. The fees contract calculates the fee of every forwarding if it's zero.
+    #[private]
+    pub fn set_forwarder_fee_cache_ttl(&mut self, forwarder_id: AccountId, blocks: U64) -> Promise {
+        ext_forwarder::ext(forwarder_id).set_fee_cache_ttl(blocks.0)
+    }
+
+    /// Allow forwarding the token by a specific forwarder.
+    #[private]
+    pub fn allow_forwarder_token(
+        &mut self,
+        forwarder_id: AccountId,
+        token_id: AccountId,
+    ) -> Promise {
+        ext_forwarder::ext(forwarder_id).allow_token(token_id)
+    }
+
+    /// Deny forwarding the token by a specific forwarder.
+    #[private]
+    pub fn deny_forwarder_token(
+        &mut self,
+        forwarder_id: AccountId,
+        token_id: AccountId,
+    ) -> Promise {
+        ext_forwarder::ext(forwarder_id).deny_token(token_id)
+    }
+
+    /// Remove the token from the allowlist and the denylist of a specific forwarder.
+    #[private]
+    pub fn remove_forwarder_token(
+        &mut self,
+        forwarder_id: AccountId,
+        token_id: AccountId,
+    ) -> Promise {
+        ext_forwarder::ext(forwarder_id).remove_token(token_id)
+    }
+
+    /// Transfer the tokens which were refunded to a specific forwarder by the target network to
+    /// the receiver.
+    #[private]
+    pub fn reclaim_forwarder_tokens(
+        &mut self,
+        forwarder_id: AccountId,
+        token_id: AccountId,
+        receiver_id: AccountId,
+    ) -> Promise {
+        ext_forwarder::ext(forwarder_id).reclaim(token_id, receiver_id)
+    }
+
+    /// Upgrade a specific forwarder to the code embedded into the factory.
+    #[private]
+    pub fn upgrade_forwarder(&mut self, forwarder_id: AccountId) -> Promise {
+        Promise::new(forwarder_id).function_call(
+            "upgrade".to_string(),
+            FORWARDER_WASM.to_vec(),
+            NearToken::from_near(0),
+            UPGRADE_FORWARDER_GAS,
+        )
     }
 
     /// Set new fees contract id.
@@ -125,7 +373,36 @@ pub trait ExtToken {
 
 #[ext_contract(ext_forwarder)]
 pub trait ExtForwarder {
-    fn forward(&self, #[serializer(borsh)] token_id: AccountId);
+    fn forward(
+        &self,
+        #[serializer(borsh)] token_id: AccountId,
+        #[serializer(borsh)] amount: Option<u128>,
+    );
+    fn forward_many(&self, #[serializer(borsh)] token_ids: Vec<AccountId>);
+    fn set_target(&self, #[serializer(borsh)] target_address: Address);
+    fn set_targets(&self, #[serializer(borsh)] targets: Vec<(Address, u32)>);
+    fn set_target_network(&self, #[serializer(borsh)] target_network: AccountId);
+    fn set_target_account(&self, #[serializer(borsh)] target_account_id: AccountId);
+    fn set_msg_format(&self, #[serializer(borsh)] msg_format: MsgFormat);
+    fn set_reserve_margin(&self, #[serializer(borsh)] reserve_margin: u128);
+    fn unlock(&self, #[serializer(borsh)] token_id: AccountId);
+    fn reclaim(
+        &self,
+        #[serializer(borsh)] token_id: AccountId,
+        #[serializer(borsh)] receiver_id: AccountId,
+    );
+    fn set_transfer_gas(
+        &self,
+        #[serializer(borsh)] token_id: AccountId,
+        #[serializer(borsh)] gas: Option<u64>,
+    );
+    fn set_fee_cache_ttl(&self, #[serializer(borsh)] blocks: u64);
+    fn set_caller_policy(&self, #[serializer(borsh)] caller_policy: CallerPolicy);
+    fn add_relayer(&self, #[serializer(borsh)] relayer_id: AccountId);
+    fn remove_relayer(&self, #[serializer(borsh)] relayer_id: AccountId);
+    fn allow_token(&self, #[serializer(borsh)] token_id: AccountId);
+    fn deny_token(&self, #[serializer(borsh)] token_id: AccountId);
+    fn remove_token(&self, #[serializer(borsh)] token_id: AccountId);
     fn destroy(&self);
 }
 
@@ -135,16 +412,80 @@ pub struct DeployParameters {
     pub target_address: String,
     pub target_network: AccountId,
     pub wnear_contract_id: AccountId,
+    /// Don't add the full-access key of the updater to the forwarder. Such a forwarder could be
+    /// upgraded by the factory only.
+    #[serde(default)]
+    pub skip_full_access_key: bool,
+    /// Maximum ratio of the fee to the forwarded amount in basis points. The forwarder uses its
+    /// default ratio if it's omitted.
+    #[serde(default)]
+    pub max_fee_bps: Option<u16>,
+    /// NEAR account the forwarder sends the tokens to with `ft_transfer`. The target address and
+    /// network are ignored if it's set.
+    #[serde(default)]
+    pub target_account_id: Option<AccountId>,
 }
 
-#[derive(BorshSerialize)]
+/// Format of the `msg` of `ft_transfer_call` expected by the target network. The order of the
+/// variants must match the one of the forwarder.
+#[derive(Deserialize, Serialize, BorshSerialize)]
+#[serde(crate = "near_sdk::serde", tag = "format", rename_all = "snake_case")]
 #[borsh(crate = "near_sdk::borsh")]
+pub enum MsgFormat {
+    /// Hex address, which is expected by the Aurora engine.
+    Address,
+    /// `<relayer_id>:<fee><address>`, which is expected by the silos paying the relayers.
+    RelayerFee { relayer_id: AccountId, fee: U128 },
+    /// `<chain>:0x<address>`, which is expected by the bridge lockers.
+    ChainPrefixed { chain: String },
+    /// JSON transfer message of the Omni bridge.
+    Omni { chain: String, fee: U128 },
+}
+
+/// Accounts which could start the forwarding in addition to the owner. The order of the variants
+/// must match the one of the forwarder.
+#[derive(Deserialize, Serialize, BorshSerialize)]
+#[serde(crate = "near_sdk::serde", rename_all = "snake_case")]
+#[borsh(crate = "near_sdk::borsh")]
+pub enum CallerPolicy {
+    Anyone,
+    Owner,
+    /// The relayers added with `add_forwarder_relayer`.
+    Relayers,
+}
+
 pub struct ForwarderParameters<'a> {
     pub target_address: Address,
     pub target_network: &'a AccountId,
     pub wnear_contract_id: &'a AccountId,
     pub fees_contract_id: &'a AccountId,
     pub owner_id: &'a AccountId,
+    pub add_full_access_key: bool,
+    /// NEAR account the tokens are sent to instead of the target address.
+    pub target_account_id: Option<&'a AccountId>,
+    pub max_fee_bps: Option<u16>,
+}
+
+impl BorshSerialize for ForwarderParameters<'_> {
+    fn serialize<W: borsh::io::Write>(&self, writer: &mut W) -> borsh::io::Result<()> {
+        BorshSerialize::serialize(
+            &(
+                &self.target_address,
+                self.target_network,
+                self.wnear_contract_id,
+                self.fees_contract_id,
+                self.owner_id,
+                self.add_full_access_key,
+                self.target_account_id,
+            ),
+            writer,
+        )?;
+
+        // The maximum fee ratio is a trailing field, which is omitted to use the default one.
+        self.max_fee_bps.map_or(Ok(()), |max_fee_bps| {
+            BorshSerialize::serialize(&max_fee_bps, writer)
+        })
+    }
 }
 
 fn create_forwarder_id(
@@ -1,7 +1,9 @@
 use aurora_engine_types::types::Address;
 use near_sdk::borsh::{BorshDeserialize, BorshSerialize};
 use near_sdk::json_types::{U128, U64};
-use near_sdk::{env, near_bindgen, AccountId, IntoStorageKey, PanicOnDefault};
+use near_sdk::{
+    env, near_bindgen, AccountId, Gas, IntoStorageKey, NearToken, PanicOnDefault, Promise,
+};
 use std::collections::BTreeSet;
 use std::error::Error;
 use std::fmt::{Display, Formatter};
@@ -12,6 +14,7 @@ use std::str::FromStr;
 const MIN_FEE_PERCENT: u64 = 1; // 0.01 %
 const MAX_FEE_PERCENT: u64 = 1000; // 10 %
 const DEFAULT_PERCENT: U64 = U64(500); // 5%
+const CLAIM_FEES_GAS: Gas = Gas::from_tgas(20);
 
 #[near_bindgen]
 #[derive(BorshDeserialize, BorshSerialize, PanicOnDefault)]
@@ -75,6 +78,38 @@ impl FeesCalculator {
         }
     }
 
+    /// Calculate and return the fee for the tokens split between several addresses on the
+    /// corresponding Aurora Network. The fee is calculated for the whole amount. The tokens sent
+    /// to a NEAR account are passed with the account as the network and without addresses.
+    #[must_use]
+    #[result_serializer(borsh)]
+    #[allow(clippy::needless_pass_by_value)]
+    pub fn calculate_split_fees(
+        &self,
+        #[serializer(borsh)] amount: U128,
+        #[serializer(borsh)] token_id: &AccountId,
+        #[serializer(borsh)] target_network: &AccountId,
+        #[serializer(borsh)] targets: Vec<(Address, u32)>,
+    ) -> U128 {
+        let target_address = targets
+            .first()
+            .map(|(address, _)| *address)
+            .unwrap_or_default();
+
+        self.calculate_fees(amount, token_id, target_network, target_address)
+    }
+
+    /// Return the fee rate of the token in hundredths of a percent, or zero if there is no fee.
+    /// The forwarders cache the rate and calculate the fee the same way as `calculate_fees`.
+    #[must_use]
+    #[result_serializer(borsh)]
+    pub fn get_fee_rate(&self, #[serializer(borsh)] token_id: &AccountId) -> U64 {
+        match self.percent {
+            Some(percent) if self.supported_tokens.contains(token_id) => percent,
+            _ => U64(0),
+        }
+    }
+
     /// Set the percent of the fee.
     ///
     /// # Panics
@@ -108,6 +143,28 @@ impl FeesCalculator {
         self.supported_tokens.iter().collect()
     }
 
+    /// Claim the fees of the token accrued by the forwarder, which couldn't be transferred to the
+    /// contract, e.g. because the contract wasn't registered on the token.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the invoker of the transaction is not owner.
+    #[must_use]
+    pub fn claim_fees(&self, forwarder_id: AccountId, token_id: &AccountId) -> Promise {
+        assert_eq!(
+            env::predecessor_account_id(),
+            self.owner,
+            "Only owner can claim fees"
+        );
+
+        Promise::new(forwarder_id).function_call(
+            "claim_fees".to_string(),
+            near_sdk::borsh::to_vec(token_id).unwrap_or_default(),
+            NearToken::from_near(0),
+            CLAIM_FEES_GAS,
+        )
+    }
+
     /// Add an account id of a new supported NEP-141 token.
     ///
     /// # Panics
@@ -268,6 +325,22 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_calculate_split_fees() {
+        let aurora = "aurora".parse().unwrap();
+        let usdt: AccountId = "usdt.near".parse().unwrap();
+        let contract = FeesCalculator::new(vec![usdt.clone()]);
+        let targets = vec![
+            (Address::from_array([1; 20]), 70),
+            (Address::from_array([2; 20]), 30),
+        ];
+
+        assert_eq!(
+            contract.calculate_split_fees(1000.into(), &usdt, &aurora, targets),
+            50.into()
+        );
+    }
+
     #[test]
     fn test_fee_is_never_rounded_to_zero_for_small_numbers() {
         let aurora = "aurora".parse().unwrap();
@@ -311,6 +384,20 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_get_fee_rate() {
+        let usdt: AccountId = "usdt.near".parse().unwrap();
+        let mut contract = FeesCalculator::new(vec![]);
+
+        assert_eq!(contract.get_fee_rate(&usdt), 0.into());
+
+        contract.add_supported_token(usdt.clone());
+        assert_eq!(contract.get_fee_rate(&usdt), 500.into());
+
+        contract.set_fee_percent(Some("0".to_string()));
+        assert_eq!(contract.get_fee_rate(&usdt), 0.into());
+    }
+
     #[test]
     fn test_set_percent() {
         let mut contract = FeesCalculator::new(vec![]);
@@ -0,0 +1,234 @@
+//! Methods exported to the NEAR runtime. They pass the runtime to the implementations, which are
+//! generic over the runtime, so the implementations could be tested with the mock runtime.
+
+use crate::runtime::Runtime;
+
+#[no_mangle]
+pub extern "C" fn new() {
+    crate::new(Runtime);
+}
+
+#[no_mangle]
+pub extern "C" fn upgrade() {
+    crate::upgrade(Runtime);
+}
+
+#[no_mangle]
+pub extern "C" fn migrate() {
+    crate::migrate(Runtime);
+}
+
+#[no_mangle]
+pub extern "C" fn forward() {
+    crate::forward(Runtime);
+}
+
+#[no_mangle]
+pub extern "C" fn forward_many() {
+    crate::forward_many(Runtime);
+}
+
+#[no_mangle]
+pub extern "C" fn calculate_fees_callback() {
+    crate::calculate_fees_callback(Runtime);
+}
+
+#[no_mangle]
+pub extern "C" fn ft_on_transfer() {
+    crate::ft_on_transfer(Runtime);
+}
+
+#[no_mangle]
+pub extern "C" fn finish_forward_callback() {
+    crate::finish_forward_callback(Runtime);
+}
+
+#[no_mangle]
+pub extern "C" fn resolve_forward_callback() {
+    crate::resolve_forward_callback(Runtime);
+}
+
+#[no_mangle]
+pub extern "C" fn resolve_fee_callback() {
+    crate::resolve_fee_callback(Runtime);
+}
+
+#[no_mangle]
+pub extern "C" fn resolve_reclaim_callback() {
+    crate::resolve_reclaim_callback(Runtime);
+}
+
+#[no_mangle]
+pub extern "C" fn claim_fees() {
+    crate::claim_fees(Runtime);
+}
+
+#[no_mangle]
+pub extern "C" fn reclaim() {
+    crate::reclaim(Runtime);
+}
+
+#[no_mangle]
+pub extern "C" fn allow_token() {
+    crate::allow_token(Runtime);
+}
+
+#[no_mangle]
+pub extern "C" fn deny_token() {
+    crate::deny_token(Runtime);
+}
+
+#[no_mangle]
+pub extern "C" fn remove_token() {
+    crate::remove_token(Runtime);
+}
+
+#[no_mangle]
+pub extern "C" fn set_transfer_gas() {
+    crate::set_transfer_gas(Runtime);
+}
+
+#[no_mangle]
+pub extern "C" fn set_caller_policy() {
+    crate::set_caller_policy(Runtime);
+}
+
+#[no_mangle]
+pub extern "C" fn add_relayer() {
+    crate::add_relayer(Runtime);
+}
+
+#[no_mangle]
+pub extern "C" fn remove_relayer() {
+    crate::remove_relayer(Runtime);
+}
+
+#[no_mangle]
+pub extern "C" fn set_fee_cache_ttl() {
+    crate::set_fee_cache_ttl(Runtime);
+}
+
+#[no_mangle]
+pub extern "C" fn unlock() {
+    crate::unlock(Runtime);
+}
+
+#[no_mangle]
+pub extern "C" fn set_target() {
+    crate::set_target(Runtime);
+}
+
+#[no_mangle]
+pub extern "C" fn set_targets() {
+    crate::set_targets(Runtime);
+}
+
+#[no_mangle]
+pub extern "C" fn set_targets_callback() {
+    crate::set_targets_callback(Runtime);
+}
+
+#[no_mangle]
+pub extern "C" fn set_target_network() {
+    crate::set_target_network(Runtime);
+}
+
+#[no_mangle]
+pub extern "C" fn set_target_account() {
+    crate::set_target_account(Runtime);
+}
+
+#[no_mangle]
+pub extern "C" fn set_msg_format() {
+    crate::set_msg_format(Runtime);
+}
+
+#[no_mangle]
+pub extern "C" fn set_reserve_margin() {
+    crate::set_reserve_margin(Runtime);
+}
+
+#[no_mangle]
+pub extern "C" fn get_state() {
+    crate::get_state(Runtime);
+}
+
+#[no_mangle]
+pub extern "C" fn get_target_address() {
+    crate::get_target_address(Runtime);
+}
+
+#[no_mangle]
+pub extern "C" fn get_targets() {
+    crate::get_targets(Runtime);
+}
+
+#[no_mangle]
+pub extern "C" fn get_target_network() {
+    crate::get_target_network(Runtime);
+}
+
+#[no_mangle]
+pub extern "C" fn get_target_account_id() {
+    crate::get_target_account_id(Runtime);
+}
+
+#[no_mangle]
+pub extern "C" fn get_native_reserve() {
+    crate::get_native_reserve(Runtime);
+}
+
+#[no_mangle]
+pub extern "C" fn get_accrued_fee() {
+    crate::get_accrued_fee(Runtime);
+}
+
+#[no_mangle]
+pub extern "C" fn get_max_fee_bps() {
+    crate::get_max_fee_bps(Runtime);
+}
+
+#[no_mangle]
+pub extern "C" fn get_msg_format() {
+    crate::get_msg_format(Runtime);
+}
+
+#[no_mangle]
+pub extern "C" fn get_transfer_gas() {
+    crate::get_transfer_gas(Runtime);
+}
+
+#[no_mangle]
+pub extern "C" fn is_token_allowed() {
+    crate::is_token_allowed(Runtime);
+}
+
+#[no_mangle]
+pub extern "C" fn is_locked() {
+    crate::is_locked(Runtime);
+}
+
+#[no_mangle]
+pub extern "C" fn is_retargeted() {
+    crate::is_retargeted(Runtime);
+}
+
+#[no_mangle]
+pub extern "C" fn get_original_target() {
+    crate::get_original_target(Runtime);
+}
+
+#[no_mangle]
+pub extern "C" fn get_fees_contract_id() {
+    crate::get_fees_contract_id(Runtime);
+}
+
+#[no_mangle]
+pub extern "C" fn get_owner_id() {
+    crate::get_owner_id(Runtime);
+}
+
+#[no_mangle]
+pub extern "C" fn destroy() {
+    crate::destroy(Runtime);
+}
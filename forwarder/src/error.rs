@@ -1,24 +1,167 @@
+/// Failures of the forwarder. The contract panics with, or reports in the `forward_failed` event,
+/// the code of the error, e.g. `ERR_NOT_OWNER`.
+///
+/// The codes are stable: the code of an existing variant is never changed or reused.
+#[derive(Copy, Clone)]
 #[cfg_attr(not(target_arch = "wasm32"), derive(Debug, PartialEq, Eq))]
 pub enum ContractError {
     ParseAccountError,
+    ParseChainIdError,
     BorshDeserializeError,
     BorshSerializeError,
     OneYoctoAttachError,
     PrivateCallError,
     BadUtf8String,
     BadNumber,
+    AmountExceedsBalance,
+    EmptyTokenList,
+    NotEnoughGas,
+    InvalidCodeSize,
+    InvalidTargets,
+    WrongTargetKind,
+    InsufficientBalance,
+    ForwardInProgress,
+    TokenNotAllowed,
+    FeeTooHigh,
+    AlreadyInitialized,
+    NotInitialized,
+    NotOwner,
+    NotFeesContract,
+    NoPromiseResult,
+    PromiseFailed,
+    TransferRefunded,
+    NothingToClaim,
+    NothingToReclaim,
+    JsonCapacityExceeded,
+    BadJson,
+    ResultTooLarge,
+    CallerNotAllowed,
+    SplitFeesNotSupported,
 }
 
-impl AsRef<[u8]> for ContractError {
-    fn as_ref(&self) -> &[u8] {
+impl ContractError {
+    /// All the errors of the forwarder.
+    pub const ALL: [Self; 32] = [
+        Self::ParseAccountError,
+        Self::ParseChainIdError,
+        Self::BorshDeserializeError,
+        Self::BorshSerializeError,
+        Self::OneYoctoAttachError,
+        Self::PrivateCallError,
+        Self::BadUtf8String,
+        Self::BadNumber,
+        Self::AmountExceedsBalance,
+        Self::EmptyTokenList,
+        Self::NotEnoughGas,
+        Self::InvalidCodeSize,
+        Self::InvalidTargets,
+        Self::WrongTargetKind,
+        Self::InsufficientBalance,
+        Self::ForwardInProgress,
+        Self::TokenNotAllowed,
+        Self::FeeTooHigh,
+        Self::AlreadyInitialized,
+        Self::NotInitialized,
+        Self::NotOwner,
+        Self::NotFeesContract,
+        Self::NoPromiseResult,
+        Self::PromiseFailed,
+        Self::TransferRefunded,
+        Self::NothingToClaim,
+        Self::NothingToReclaim,
+        Self::JsonCapacityExceeded,
+        Self::BadJson,
+        Self::ResultTooLarge,
+        Self::CallerNotAllowed,
+        Self::SplitFeesNotSupported,
+    ];
+
+    /// The code of the error.
+    #[must_use]
+    pub const fn code(&self) -> &'static str {
         match self {
-            Self::ParseAccountError => b"ERR_PARS_ACCOUNT",
-            Self::BorshDeserializeError => b"ERR_BORCH_DESERIALIZE",
-            Self::BorshSerializeError => b"ERR_BORCH_SERIALIZE",
-            Self::OneYoctoAttachError => b"ERR_ONE_YOCTO_ATACH",
-            Self::PrivateCallError => b"ERR_PRIVATE_CALL",
-            Self::BadUtf8String => b"ERR_BAD_UTF8_STRING",
-            Self::BadNumber => b"ERR_BAD_NUMBER",
+            Self::ParseAccountError => "ERR_PARSE_ACCOUNT",
+            Self::ParseChainIdError => "ERR_PARSE_CHAIN_ID",
+            Self::BorshDeserializeError => "ERR_BORSH_DESERIALIZE",
+            Self::BorshSerializeError => "ERR_BORSH_SERIALIZE",
+            Self::OneYoctoAttachError => "ERR_ONE_YOCTO_ATTACH",
+            Self::PrivateCallError => "ERR_PRIVATE_CALL",
+            Self::BadUtf8String => "ERR_BAD_UTF8_STRING",
+            Self::BadNumber => "ERR_BAD_NUMBER",
+            Self::AmountExceedsBalance => "ERR_AMOUNT_EXCEEDS_BALANCE",
+            Self::EmptyTokenList => "ERR_EMPTY_TOKEN_LIST",
+            Self::NotEnoughGas => "ERR_NOT_ENOUGH_GAS",
+            Self::InvalidCodeSize => "ERR_INVALID_CODE_SIZE",
+            Self::InvalidTargets => "ERR_INVALID_TARGETS",
+            Self::WrongTargetKind => "ERR_WRONG_TARGET_KIND",
+            Self::InsufficientBalance => "ERR_INSUFFICIENT_BALANCE",
+            Self::ForwardInProgress => "ERR_FORWARD_IN_PROGRESS",
+            Self::TokenNotAllowed => "ERR_TOKEN_NOT_ALLOWED",
+            Self::FeeTooHigh => "ERR_FEE_TOO_HIGH",
+            Self::AlreadyInitialized => "ERR_ALREADY_INITIALIZED",
+            Self::NotInitialized => "ERR_NOT_INITIALIZED",
+            Self::NotOwner => "ERR_NOT_OWNER",
+            Self::NotFeesContract => "ERR_NOT_FEES_CONTRACT",
+            Self::NoPromiseResult => "ERR_NO_PROMISE_RESULT",
+            Self::PromiseFailed => "ERR_PROMISE_FAILED",
+            Self::TransferRefunded => "ERR_TRANSFER_REFUNDED",
+            Self::NothingToClaim => "ERR_NOTHING_TO_CLAIM",
+            Self::NothingToReclaim => "ERR_NOTHING_TO_RECLAIM",
+            Self::JsonCapacityExceeded => "ERR_JSON_CAPACITY_EXCEEDED",
+            Self::BadJson => "ERR_BAD_JSON",
+            Self::ResultTooLarge => "ERR_RESULT_TOO_LARGE",
+            Self::CallerNotAllowed => "ERR_CALLER_NOT_ALLOWED",
+            Self::SplitFeesNotSupported => "ERR_SPLIT_FEES_NOT_SUPPORTED",
         }
     }
 }
+
+#[cfg(not(target_arch = "wasm32"))]
+impl ContractError {
+    /// Finds the error of the forwarder in the message of the failed transaction or in the log
+    /// of the `forward_failed` event. Returns `None` if the message contains no error code.
+    #[must_use]
+    pub fn from_message(message: &str) -> Option<Self> {
+        message
+            .split(|c: char| !(c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_'))
+            .find_map(|word| Self::ALL.into_iter().find(|error| error.code() == word))
+    }
+}
+
+impl AsRef<[u8]> for ContractError {
+    fn as_ref(&self) -> &[u8] {
+        self.code().as_bytes()
+    }
+}
+
+#[test]
+fn test_error_codes_are_unique() {
+    for (i, error) in ContractError::ALL.iter().enumerate() {
+        assert!(error.code().starts_with("ERR_"));
+        assert!(ContractError::ALL[i + 1..]
+            .iter()
+            .all(|other| other.code() != error.code()));
+    }
+}
+
+#[test]
+fn test_error_from_message() {
+    assert_eq!(
+        ContractError::from_message("Smart contract panicked: ERR_NOT_OWNER"),
+        Some(ContractError::NotOwner)
+    );
+    assert_eq!(
+        ContractError::from_message(
+            r#"EVENT_JSON:{"event":"forward_failed","data":[{"reason":"ERR_FEE_TOO_HIGH"}]}"#
+        ),
+        Some(ContractError::FeeTooHigh)
+    );
+    assert_eq!(
+        ContractError::from_message("ERR_NOT_OWNER_OF_SOMETHING"),
+        None
+    );
+    assert_eq!(
+        ContractError::from_message("Exceeded the prepaid gas"),
+        None
+    );
+}
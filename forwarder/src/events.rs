@@ -0,0 +1,337 @@
+use arrayvec::ArrayString;
+
+use crate::error::ContractError;
+use crate::json::JsonWriter;
+use crate::params::address_to_hex;
+use crate::runtime::log_utf8;
+use crate::state::{State, Target};
+use crate::types::AccountId;
+#[cfg(test)]
+use crate::{state::Targets, types::Address};
+
+const EVENT_JSON_PREFIX: &str = "EVENT_JSON:";
+const STANDARD: &str = "aurora-forwarder";
+const VERSION: &str = "1.0.0";
+const MAX_EVENT_LEN: usize = 1024;
+
+/// Stages of the forwarding which are reported with NEP-297 events.
+#[derive(Copy, Clone)]
+pub enum ForwardEvent {
+    Started,
+    FeeQuoted,
+    TransferSent,
+    Failed,
+}
+
+impl ForwardEvent {
+    const fn name(self) -> &'static str {
+        match self {
+            Self::Started => "forward_started",
+            Self::FeeQuoted => "fee_quoted",
+            Self::TransferSent => "transfer_sent",
+            Self::Failed => "forward_failed",
+        }
+    }
+
+    /// Writes the event to the logs in the NEP-297 format.
+    pub fn emit(self, data: &EventData) {
+        emit(&self.to_json(data));
+    }
+
+    fn to_json(self, data: &EventData) -> Result<ArrayString<MAX_EVENT_LEN>, ContractError> {
+        event_json(self.name(), |writer| data.write(writer))
+    }
+}
+
+/// Change of the target made by the owner of the forwarder.
+pub struct TargetChanged<'a> {
+    pub old_target: &'a Target,
+    pub target: &'a Target,
+}
+
+impl TargetChanged<'_> {
+    /// Writes the event to the logs in the NEP-297 format.
+    pub fn emit(&self) {
+        emit(&self.to_json());
+    }
+
+    fn to_json(&self) -> Result<ArrayString<MAX_EVENT_LEN>, ContractError> {
+        event_json("target_changed", |writer| {
+            write_target(writer, "old_", self.old_target)?;
+            write_target(writer, "", self.target)
+        })
+    }
+}
+
+/// The event which doesn't fit the capacity is skipped rather than failing the forwarding.
+fn emit(json: &Result<ArrayString<MAX_EVENT_LEN>, ContractError>) {
+    if let Ok(json) = json {
+        log_utf8(json.as_bytes());
+    }
+}
+
+fn event_json(
+    name: &str,
+    write_data: impl FnOnce(&mut EventWriter) -> Result<(), ContractError>,
+) -> Result<ArrayString<MAX_EVENT_LEN>, ContractError> {
+    let mut writer = EventWriter::new();
+
+    writer.raw(EVENT_JSON_PREFIX)?;
+    writer.begin_object()?;
+    writer.str_field("standard", STANDARD)?;
+    writer.str_field("version", VERSION)?;
+    writer.str_field("event", name)?;
+    writer.key("data")?;
+    writer.begin_array()?;
+    writer.begin_object()?;
+    write_data(&mut writer)?;
+    writer.end_object()?;
+    writer.end_array()?;
+    writer.end_object()?;
+
+    Ok(writer.finish())
+}
+
+/// Writes the network and the address of the target, every address of the split target or the
+/// NEAR account. The keys are prefixed with the given prefix.
+fn write_target(
+    writer: &mut EventWriter,
+    prefix: &str,
+    target: &Target,
+) -> Result<(), ContractError> {
+    let mut field = |name: &str, value: &str| {
+        let mut prefixed = ArrayString::<32>::new();
+        prefixed.push_str(prefix);
+        prefixed.push_str(name);
+        writer.str_field(prefixed.as_str(), value)
+    };
+
+    match target {
+        Target::Evm { network, targets } if target.is_split() => {
+            field("target_network", network.as_str())?;
+            let mut key = ArrayString::<32>::new();
+            key.push_str(prefix);
+            key.push_str("target_addresses");
+            writer.key(key.as_str())?;
+            writer.begin_array()?;
+
+            for target in targets.as_slice() {
+                writer.string(address_to_hex(target.address).as_str())?;
+            }
+
+            writer.end_array()
+        }
+        Target::Evm { network, targets } => {
+            field("target_network", network.as_str())?;
+            field("target_address", address_to_hex(targets.primary()).as_str())
+        }
+        Target::Near { account_id } => field("target_account_id", account_id.as_str()),
+    }
+}
+
+/// Details of the forwarding. Values which aren't known at the current stage are omitted.
+#[derive(Copy, Clone)]
+pub struct EventData<'a> {
+    pub token_id: &'a AccountId,
+    pub amount: Option<u128>,
+    pub fee: Option<u128>,
+    pub target: &'a Target,
+    pub reason: Option<&'a [u8]>,
+}
+
+impl<'a> EventData<'a> {
+    pub const fn new(state: &'a State, token_id: &'a AccountId) -> Self {
+        Self {
+            token_id,
+            amount: None,
+            fee: None,
+            target: &state.target,
+            reason: None,
+        }
+    }
+
+    #[must_use]
+    pub const fn amount(mut self, amount: u128) -> Self {
+        self.amount = Some(amount);
+        self
+    }
+
+    #[must_use]
+    pub const fn fee(mut self, fee: u128) -> Self {
+        self.fee = Some(fee);
+        self
+    }
+
+    #[must_use]
+    pub const fn reason(mut self, reason: &'a [u8]) -> Self {
+        self.reason = Some(reason);
+        self
+    }
+
+    fn write(&self, writer: &mut EventWriter) -> Result<(), ContractError> {
+        writer.str_field("token_id", self.token_id.as_str())?;
+
+        if let Some(amount) = self.amount {
+            writer.amount_field("amount", amount)?;
+        }
+
+        if let Some(fee) = self.fee {
+            writer.amount_field("fee", fee)?;
+        }
+
+        write_target(writer, "", self.target)?;
+
+        if let Some(reason) = self.reason.and_then(|r| core::str::from_utf8(r).ok()) {
+            writer.str_field("reason", reason)?;
+        }
+
+        Ok(())
+    }
+}
+
+type EventWriter = JsonWriter<MAX_EVENT_LEN>;
+
+#[test]
+fn test_forward_event_json() {
+    let token_id = AccountId::new("usdt.near").unwrap();
+    let target = Target::Evm {
+        network: AccountId::new("aurora").unwrap(),
+        targets: Targets::single(Address([0x11; 20])),
+    };
+    let data = EventData {
+        token_id: &token_id,
+        amount: Some(1_000),
+        fee: Some(50),
+        target: &target,
+        reason: None,
+    };
+
+    assert_eq!(
+        ForwardEvent::FeeQuoted.to_json(&data).unwrap().as_str(),
+        r#"EVENT_JSON:{"standard":"aurora-forwarder","version":"1.0.0","event":"fee_quoted","data":[{"token_id":"usdt.near","amount":"1000","fee":"50","target_network":"aurora","target_address":"0x1111111111111111111111111111111111111111"}]}"#
+    );
+}
+
+#[test]
+fn test_forward_event_json_without_optional_fields() {
+    let token_id = AccountId::new("usdt.near").unwrap();
+    let target = Target::Evm {
+        network: AccountId::new("aurora").unwrap(),
+        targets: Targets::single(Address([0; 20])),
+    };
+    let data = EventData {
+        token_id: &token_id,
+        amount: None,
+        fee: None,
+        target: &target,
+        reason: Some(b"ERR_BAD_NUMBER"),
+    };
+
+    assert_eq!(
+        ForwardEvent::Failed.to_json(&data).unwrap().as_str(),
+        r#"EVENT_JSON:{"standard":"aurora-forwarder","version":"1.0.0","event":"forward_failed","data":[{"token_id":"usdt.near","target_network":"aurora","target_address":"0x0000000000000000000000000000000000000000","reason":"ERR_BAD_NUMBER"}]}"#
+    );
+}
+
+#[test]
+fn test_target_changed_event_json() {
+    let old_target = Target::Evm {
+        network: AccountId::new("aurora").unwrap(),
+        targets: Targets::single(Address([0x11; 20])),
+    };
+    let target = Target::Evm {
+        network: AccountId::new("silo.aurora").unwrap(),
+        targets: Targets::single(Address([0x22; 20])),
+    };
+    let event = TargetChanged {
+        old_target: &old_target,
+        target: &target,
+    };
+
+    assert_eq!(
+        event.to_json().unwrap().as_str(),
+        r#"EVENT_JSON:{"standard":"aurora-forwarder","version":"1.0.0","event":"target_changed","data":[{"old_target_network":"aurora","old_target_address":"0x1111111111111111111111111111111111111111","target_network":"silo.aurora","target_address":"0x2222222222222222222222222222222222222222"}]}"#
+    );
+}
+
+#[test]
+fn test_near_target_event_json() {
+    let token_id = AccountId::new("usdt.near").unwrap();
+    let target = Target::Near {
+        account_id: AccountId::new("exchange.near").unwrap(),
+    };
+    let data = EventData {
+        token_id: &token_id,
+        amount: Some(1_000),
+        fee: None,
+        target: &target,
+        reason: None,
+    };
+
+    assert_eq!(
+        ForwardEvent::Started.to_json(&data).unwrap().as_str(),
+        r#"EVENT_JSON:{"standard":"aurora-forwarder","version":"1.0.0","event":"forward_started","data":[{"token_id":"usdt.near","amount":"1000","target_account_id":"exchange.near"}]}"#
+    );
+
+    let old_target = Target::Evm {
+        network: AccountId::new("aurora").unwrap(),
+        targets: Targets::single(Address([0x11; 20])),
+    };
+    let event = TargetChanged {
+        old_target: &old_target,
+        target: &target,
+    };
+
+    assert_eq!(
+        event.to_json().unwrap().as_str(),
+        r#"EVENT_JSON:{"standard":"aurora-forwarder","version":"1.0.0","event":"target_changed","data":[{"old_target_network":"aurora","old_target_address":"0x1111111111111111111111111111111111111111","target_account_id":"exchange.near"}]}"#
+    );
+}
+
+#[test]
+fn test_split_target_event_json() {
+    use crate::state::{WeightedAddress, MAX_TARGETS};
+
+    let targets = |count: u8| {
+        (1..=count)
+            .map(|i| WeightedAddress {
+                address: Address([i * 0x11; 20]),
+                weight: 1,
+            })
+            .collect::<arrayvec::ArrayVec<_, MAX_TARGETS>>()
+            .into()
+    };
+    let old_target = Target::Evm {
+        network: AccountId::new("aurora").unwrap(),
+        targets: Targets::single(Address([0x11; 20])),
+    };
+    let target = Target::Evm {
+        network: AccountId::new("aurora").unwrap(),
+        targets: Targets::new(targets(2)).unwrap(),
+    };
+    let event = TargetChanged {
+        old_target: &old_target,
+        target: &target,
+    };
+
+    assert_eq!(
+        event.to_json().unwrap().as_str(),
+        r#"EVENT_JSON:{"standard":"aurora-forwarder","version":"1.0.0","event":"target_changed","data":[{"old_target_network":"aurora","old_target_address":"0x1111111111111111111111111111111111111111","target_network":"aurora","target_addresses":["0x1111111111111111111111111111111111111111","0x2222222222222222222222222222222222222222"]}]}"#
+    );
+
+    // The longest event fits the buffer.
+    let token_id = AccountId::new(&"a".repeat(64)).unwrap();
+    let target = Target::Evm {
+        network: AccountId::new(&"b".repeat(64)).unwrap(),
+        targets: Targets::new(targets(4)).unwrap(),
+    };
+    let data = EventData {
+        token_id: &token_id,
+        amount: Some(u128::MAX),
+        fee: Some(u128::MAX),
+        target: &target,
+        reason: Some(b"ERR_JSON_CAPACITY_EXCEEDED"),
+    };
+
+    assert!(ForwardEvent::Failed.to_json(&data).is_ok());
+}
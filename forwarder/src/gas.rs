@@ -0,0 +1,231 @@
+use crate::error::ContractError;
+use crate::math;
+
+const TGAS: u64 = 1_000_000_000_000;
+
+/// Gas kept by every method of the forwarder for its own execution.
+pub const EXECUTION_GAS: u64 = 10 * TGAS;
+/// Gas of the `ft_transfer` of the fee or the reclaimed tokens.
+pub const FT_TRANSFER_GAS: u64 = 3 * TGAS;
+/// Gas of the callback recording the fee if its transfer fails.
+pub const RESOLVE_FEE_GAS: u64 = 5 * TGAS;
+/// Gas of the callback recording the reclaimed tokens as refunded again if their transfer fails.
+pub const RESOLVE_RECLAIM_GAS: u64 = 5 * TGAS;
+/// Gas of the call of `migrate` after the code is deployed.
+pub const MIGRATE_GAS: u64 = 10 * TGAS;
+/// Gas of every transfer to the targets unless it's overridden for the token. It's low enough for
+/// the transfers to the maximum number of the targets to fit the gas of a single call.
+pub const DEFAULT_TRANSFER_GAS: u64 = 50 * TGAS;
+/// Maximum gas which could be attached to a call.
+pub const MAX_CALL_GAS: u64 = 300 * TGAS;
+
+pub const FT_BALANCE: Stage = Stage::fixed(2 * TGAS);
+pub const NEAR_DEPOSIT: Stage = Stage::fixed(2 * TGAS);
+pub const CALCULATE_FEES: Stage = Stage::fixed(4 * TGAS);
+pub const FEE_RATE: Stage = Stage::fixed(2 * TGAS);
+pub const RESOLVE_FORWARD: Stage = Stage::new(EXECUTION_GAS + FT_TRANSFER_GAS + RESOLVE_FEE_GAS, 1);
+
+const EXECUTION: Stage = Stage::fixed(EXECUTION_GAS);
+const TRANSFERS_WEIGHT: u64 = 8;
+
+/// Stage of the forwarding, which gets at least the minimum gas and the share of the rest of the
+/// gas proportional to its weight.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Stage {
+    pub min: u64,
+    pub weight: u64,
+}
+
+impl Stage {
+    #[must_use]
+    pub const fn new(min: u64, weight: u64) -> Self {
+        Self { min, weight }
+    }
+
+    /// The stage which gets the minimum gas only.
+    #[must_use]
+    pub const fn fixed(min: u64) -> Self {
+        Self::new(min, 0)
+    }
+
+    /// Combines the stage with the one that is executed after it.
+    #[must_use]
+    pub const fn then(self, other: Self) -> Self {
+        Self::new(
+            self.min.saturating_add(other.min),
+            self.weight.saturating_add(other.weight),
+        )
+    }
+}
+
+/// The transfers of the forwarded tokens to the targets. Every transfer gets at least the
+/// transfer gas, so the minimum gas of the stage grows with the number of the transfers.
+#[must_use]
+pub const fn transfers(transfer_gas: u64, count: usize) -> Stage {
+    Stage::new(transfer_gas.saturating_mul(count as u64), TRANSFERS_WEIGHT)
+}
+
+/// The `finish_forward_callback` with the transfers and the resolving of their results.
+#[must_use]
+pub const fn finish_forward(transfer_gas: u64, count: usize) -> Stage {
+    EXECUTION
+        .then(transfers(transfer_gas, count))
+        .then(RESOLVE_FORWARD)
+}
+
+/// The `calculate_fees_callback` with the rest of the forwarding of the NEP-141 token.
+#[must_use]
+pub const fn calculate_fees_callback(transfer_gas: u64, count: usize) -> Stage {
+    EXECUTION
+        .then(CALCULATE_FEES)
+        .then(finish_forward(transfer_gas, count))
+}
+
+/// Stages of the forwarding of native NEAR started by `forward`.
+#[must_use]
+pub const fn forward_native(transfer_gas: u64, count: usize) -> [Stage; 3] {
+    [
+        NEAR_DEPOSIT,
+        CALCULATE_FEES,
+        finish_forward(transfer_gas, count),
+    ]
+}
+
+/// Stages of the forwarding of the NEP-141 token started by `forward`.
+#[must_use]
+pub const fn forward_nep141(transfer_gas: u64, count: usize) -> [Stage; 2] {
+    [FT_BALANCE, calculate_fees_callback(transfer_gas, count)]
+}
+
+/// Stages of the forwarding of the NEP-141 token started by `forward`, which reads the fee rate
+/// along with the balance to cache it.
+#[must_use]
+pub const fn forward_nep141_with_fee_rate(transfer_gas: u64, count: usize) -> [Stage; 3] {
+    [
+        FT_BALANCE,
+        FEE_RATE,
+        calculate_fees_callback(transfer_gas, count),
+    ]
+}
+
+/// Stages of the forwarding of the NEP-141 token once its amount is known.
+#[must_use]
+pub const fn calculate_fees(transfer_gas: u64, count: usize) -> [Stage; 2] {
+    [CALCULATE_FEES, finish_forward(transfer_gas, count)]
+}
+
+/// Checks that the forwarding to the given number of the targets with the default transfer gas
+/// fits the gas of a single call, so the split tokens could be forwarded at all.
+pub fn check_targets(count: usize) -> Result<(), ContractError> {
+    let forwarding = total(&forward_nep141_with_fee_rate(DEFAULT_TRANSFER_GAS, count));
+
+    if EXECUTION_GAS.saturating_add(forwarding.min) > MAX_CALL_GAS {
+        return Err(ContractError::NotEnoughGas);
+    }
+
+    Ok(())
+}
+
+/// Combines the stages into the one.
+#[must_use]
+pub fn total(stages: &[Stage]) -> Stage {
+    stages.iter().fold(Stage::fixed(0), |acc, s| acc.then(*s))
+}
+
+/// Gas of the stage out of the gas of all the stages: the minimum gas of the stage and the share
+/// of the rest of the gas proportional to the weight of the stage.
+#[must_use]
+pub fn share(gas: u64, stage: Stage, total: Stage) -> u64 {
+    let extra = gas.saturating_sub(total.min);
+    // The share never exceeds the extra gas, since the weight of the stage is a part of the total.
+    let share = math::mul_div(
+        u128::from(extra),
+        u128::from(stage.weight),
+        u128::from(total.weight),
+    )
+    .and_then(|share| u64::try_from(share).ok())
+    .unwrap_or_default();
+
+    stage.min.saturating_add(share)
+}
+
+/// Splits the gas between the stages. Every stage gets its minimum gas and the share of the rest
+/// proportional to its weight. Fails if the gas doesn't cover the minimums of all the stages.
+pub fn split<const N: usize>(gas: u64, stages: [Stage; N]) -> Result<[u64; N], ContractError> {
+    let total = total(&stages);
+
+    if gas < total.min {
+        return Err(ContractError::NotEnoughGas);
+    }
+
+    Ok(stages.map(|stage| share(gas, stage, total)))
+}
+
+#[test]
+fn test_split_gas() {
+    let stages = [FT_BALANCE, calculate_fees_callback(DEFAULT_TRANSFER_GAS, 1)];
+    let min = FT_BALANCE.min + stages[1].min;
+
+    assert_eq!(split(min - 1, stages), Err(ContractError::NotEnoughGas));
+    assert_eq!(split(min, stages), Ok([FT_BALANCE.min, stages[1].min]));
+    // The fixed stages get their minimum only.
+    assert_eq!(
+        split(min + 100 * TGAS, stages),
+        Ok([FT_BALANCE.min, stages[1].min + 100 * TGAS])
+    );
+
+    let stages = [transfers(DEFAULT_TRANSFER_GAS, 1), RESOLVE_FORWARD];
+    let min = DEFAULT_TRANSFER_GAS + RESOLVE_FORWARD.min;
+
+    assert_eq!(
+        split(min + 90 * TGAS, stages),
+        Ok([
+            DEFAULT_TRANSFER_GAS + 80 * TGAS,
+            RESOLVE_FORWARD.min + 10 * TGAS
+        ])
+    );
+}
+
+#[test]
+fn test_check_targets() {
+    for count in 1..=crate::state::MAX_TARGETS {
+        assert_eq!(check_targets(count), Ok(()));
+    }
+
+    assert_eq!(check_targets(6), Err(ContractError::NotEnoughGas));
+}
+
+#[test]
+fn test_share_gas() {
+    let total = total(&[transfers(DEFAULT_TRANSFER_GAS, 1), RESOLVE_FORWARD]);
+
+    assert_eq!(
+        share(total.min, RESOLVE_FORWARD, total),
+        RESOLVE_FORWARD.min
+    );
+    assert_eq!(
+        share(total.min + 90 * TGAS, RESOLVE_FORWARD, total),
+        RESOLVE_FORWARD.min + 10 * TGAS
+    );
+    assert_eq!(
+        share(total.min + 90 * TGAS, FT_BALANCE, total.then(FT_BALANCE)),
+        FT_BALANCE.min
+    );
+}
+
+#[test]
+fn test_nested_stages() {
+    let transfer_gas = 50 * TGAS;
+    let callback = calculate_fees_callback(transfer_gas, 1);
+
+    assert_eq!(
+        callback.min,
+        3 * EXECUTION_GAS + CALCULATE_FEES.min + transfer_gas + FT_TRANSFER_GAS + RESOLVE_FEE_GAS
+    );
+    assert_eq!(callback.weight, TRANSFERS_WEIGHT + RESOLVE_FORWARD.weight);
+    // Every transfer to the targets gets the transfer gas.
+    assert_eq!(
+        calculate_fees_callback(transfer_gas, 4).min,
+        callback.min + 3 * transfer_gas
+    );
+}
@@ -0,0 +1,465 @@
+//! Bounded JSON writer and reader for the arguments and the results of the cross-contract calls,
+//! the view methods and the events. Nothing is allocated: the JSON is written to a buffer of
+//! the fixed capacity, and the writer fails with `ERR_JSON_CAPACITY_EXCEEDED` instead of
+//! panicking if the JSON doesn't fit.
+
+use arrayvec::ArrayString;
+
+use crate::error::ContractError;
+use crate::params::amount_to_str;
+use crate::types::Vec;
+
+/// Writes the JSON to the buffer of `N` bytes. The commas between the values and the fields are
+/// inserted by the writer, and the strings are escaped.
+pub struct JsonWriter<const N: usize>(ArrayString<N>);
+
+impl<const N: usize> JsonWriter<N> {
+    #[must_use]
+    pub const fn new() -> Self {
+        Self(ArrayString::new_const())
+    }
+
+    /// Writes the text as is.
+    pub fn raw(&mut self, value: &str) -> Result<(), ContractError> {
+        self.0
+            .try_push_str(value)
+            .map_err(|_| ContractError::JsonCapacityExceeded)
+    }
+
+    /// Writes the hex representation of the bytes as is.
+    pub fn hex(&mut self, bytes: &[u8]) -> Result<(), ContractError> {
+        hex::BytesToHexIter::new(bytes.iter().copied()).try_for_each(|c| {
+            self.0
+                .try_push(c)
+                .map_err(|_| ContractError::JsonCapacityExceeded)
+        })
+    }
+
+    pub fn begin_object(&mut self) -> Result<(), ContractError> {
+        self.separator()?;
+        self.raw("{")
+    }
+
+    pub fn end_object(&mut self) -> Result<(), ContractError> {
+        self.raw("}")
+    }
+
+    pub fn begin_array(&mut self) -> Result<(), ContractError> {
+        self.separator()?;
+        self.raw("[")
+    }
+
+    pub fn end_array(&mut self) -> Result<(), ContractError> {
+        self.raw("]")
+    }
+
+    pub fn key(&mut self, key: &str) -> Result<(), ContractError> {
+        self.separator()?;
+        self.quoted(key)?;
+        self.raw(":")
+    }
+
+    pub fn string(&mut self, value: &str) -> Result<(), ContractError> {
+        self.separator()?;
+        self.quoted(value)
+    }
+
+    pub fn str_field(&mut self, key: &str, value: &str) -> Result<(), ContractError> {
+        self.key(key)?;
+        self.quoted(value)
+    }
+
+    /// Writes the amount as the string, since the JSON numbers can't hold `u128`.
+    pub fn amount_field(&mut self, key: &str, amount: u128) -> Result<(), ContractError> {
+        self.str_field(key, amount_to_str(amount).as_str())
+    }
+
+    pub fn bool_field(&mut self, key: &str, value: bool) -> Result<(), ContractError> {
+        self.key(key)?;
+        self.raw(if value { "true" } else { "false" })
+    }
+
+    pub fn number_field(&mut self, key: &str, value: u128) -> Result<(), ContractError> {
+        self.key(key)?;
+        self.raw(amount_to_str(value).as_str())
+    }
+
+    #[must_use]
+    pub const fn finish(self) -> ArrayString<N> {
+        self.0
+    }
+
+    pub fn into_vec(self) -> Result<Vec<u8>, ContractError> {
+        Vec::try_from(self.0.as_bytes()).map_err(|_| ContractError::JsonCapacityExceeded)
+    }
+
+    /// The values and the keys are separated by the comma unless they open the object or the
+    /// array, or follow the key.
+    fn separator(&mut self) -> Result<(), ContractError> {
+        match self.0.chars().last() {
+            None | Some('{' | '[' | ':') => Ok(()),
+            Some(_) => self.raw(","),
+        }
+    }
+
+    fn quoted(&mut self, value: &str) -> Result<(), ContractError> {
+        self.raw("\"")?;
+        self.escaped(value)?;
+        self.raw("\"")
+    }
+
+    fn escaped(&mut self, value: &str) -> Result<(), ContractError> {
+        for c in value.chars() {
+            match c {
+                '"' => self.raw(r#"\""#)?,
+                '\\' => self.raw(r"\\")?,
+                '\n' => self.raw(r"\n")?,
+                '\r' => self.raw(r"\r")?,
+                '\t' => self.raw(r"\t")?,
+                c if c.is_ascii_control() => {
+                    self.raw(r"\u00")?;
+                    self.hex(&[u8::try_from(c).unwrap_or_default()])?;
+                }
+                c => self
+                    .0
+                    .try_push(c)
+                    .map_err(|_| ContractError::JsonCapacityExceeded)?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl<const N: usize> Default for JsonWriter<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Minimal reader of the JSON values, which is enough to read the fields of the objects and the
+/// amounts.
+pub struct JsonReader<'a> {
+    input: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> JsonReader<'a> {
+    #[must_use]
+    pub const fn new(input: &'a [u8]) -> Self {
+        Self { input, pos: 0 }
+    }
+
+    /// Returns the reader of the value of the field of the top-level object. The other fields
+    /// are skipped. Fails with `ERR_BAD_JSON` if there is no such field.
+    pub fn field(input: &'a [u8], key: &str) -> Result<Self, ContractError> {
+        let mut reader = Self::new(input);
+        reader.expect(b'{')?;
+
+        if reader.peek() == Some(b'}') {
+            return Err(ContractError::BadJson);
+        }
+
+        loop {
+            let field = reader.read_str()?;
+            reader.expect(b':')?;
+
+            if field == key {
+                return Ok(reader);
+            }
+
+            reader.skip_value()?;
+
+            match reader.next() {
+                Some(b',') => {}
+                _ => return Err(ContractError::BadJson),
+            }
+        }
+    }
+
+    /// Reads the string. The escape sequences are kept as is.
+    pub fn read_str(&mut self) -> Result<&'a str, ContractError> {
+        self.expect(b'"')?;
+        let start = self.pos;
+
+        loop {
+            match self.input.get(self.pos) {
+                Some(b'"') => break,
+                Some(b'\\') => self.pos += 2,
+                Some(_) => self.pos += 1,
+                None => return Err(ContractError::BadJson),
+            }
+        }
+
+        let value = self
+            .input
+            .get(start..self.pos)
+            .ok_or(ContractError::BadJson)?;
+        self.pos += 1;
+
+        core::str::from_utf8(value).map_err(|_| ContractError::BadUtf8String)
+    }
+
+    /// Reads the amount written as the string, e.g. `"1000"`.
+    pub fn read_amount(&mut self) -> Result<u128, ContractError> {
+        if self.peek() != Some(b'"') {
+            return Err(ContractError::BadNumber);
+        }
+
+        parse_digits(self.read_str()?.as_bytes())
+    }
+
+    /// Reads the amount written as the string or the number.
+    pub fn read_u128(&mut self) -> Result<u128, ContractError> {
+        if self.peek() == Some(b'"') {
+            return self.read_amount();
+        }
+
+        let start = self.pos;
+
+        while self.input.get(self.pos).is_some_and(u8::is_ascii_digit) {
+            self.pos += 1;
+        }
+
+        parse_digits(&self.input[start..self.pos])
+    }
+
+    /// Fails with `ERR_BAD_JSON` if anything but whitespace is left.
+    pub fn end(mut self) -> Result<(), ContractError> {
+        self.peek().map_or(Ok(()), |_| Err(ContractError::BadJson))
+    }
+
+    fn skip_value(&mut self) -> Result<(), ContractError> {
+        match self.peek() {
+            Some(b'"') => self.read_str().map(|_| ()),
+            Some(open @ (b'{' | b'[')) => {
+                let close = if open == b'{' { b'}' } else { b']' };
+                self.pos += 1;
+
+                if self.peek() == Some(close) {
+                    self.pos += 1;
+                    return Ok(());
+                }
+
+                loop {
+                    if open == b'{' {
+                        self.read_str()?;
+                        self.expect(b':')?;
+                    }
+
+                    self.skip_value()?;
+
+                    match self.next() {
+                        Some(b',') => {}
+                        Some(c) if c == close => return Ok(()),
+                        _ => return Err(ContractError::BadJson),
+                    }
+                }
+            }
+            Some(_) => {
+                // Numbers and literals run until the next delimiter.
+                let start = self.pos;
+
+                while self
+                    .input
+                    .get(self.pos)
+                    .is_some_and(|c| !matches!(c, b',' | b'}' | b']') && !c.is_ascii_whitespace())
+                {
+                    self.pos += 1;
+                }
+
+                if self.pos == start {
+                    Err(ContractError::BadJson)
+                } else {
+                    Ok(())
+                }
+            }
+            None => Err(ContractError::BadJson),
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self
+            .input
+            .get(self.pos)
+            .is_some_and(u8::is_ascii_whitespace)
+        {
+            self.pos += 1;
+        }
+    }
+
+    fn peek(&mut self) -> Option<u8> {
+        self.skip_whitespace();
+        self.input.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Option<u8> {
+        let c = self.peek()?;
+        self.pos += 1;
+        Some(c)
+    }
+
+    fn expect(&mut self, c: u8) -> Result<(), ContractError> {
+        if self.next() == Some(c) {
+            Ok(())
+        } else {
+            Err(ContractError::BadJson)
+        }
+    }
+}
+
+/// Parses the amount returned by the cross-contract call, e.g. the result of `ft_balance_of`.
+pub fn parse_u128(input: &[u8]) -> Result<u128, ContractError> {
+    let mut reader = JsonReader::new(input);
+    let value = reader.read_u128()?;
+    reader.end()?;
+
+    Ok(value)
+}
+
+/// Parses the amount at the beginning of the result which is too large to be read as a whole.
+pub fn parse_u128_prefix(input: &[u8]) -> Result<u128, ContractError> {
+    JsonReader::new(input).read_u128()
+}
+
+fn parse_digits(digits: &[u8]) -> Result<u128, ContractError> {
+    if digits.is_empty() {
+        return Err(ContractError::BadNumber);
+    }
+
+    digits.iter().try_fold(0u128, |acc, c| {
+        if !c.is_ascii_digit() {
+            return Err(ContractError::BadNumber);
+        }
+
+        acc.checked_mul(10)
+            .and_then(|acc| acc.checked_add(u128::from(c - b'0')))
+            .ok_or(ContractError::BadNumber)
+    })
+}
+
+#[test]
+fn test_write_json() {
+    let mut writer = JsonWriter::<128>::new();
+
+    writer.begin_object().unwrap();
+    writer.str_field("name", "value").unwrap();
+    writer.amount_field("amount", 10).unwrap();
+    writer.key("list").unwrap();
+    writer.begin_array().unwrap();
+    writer.string("one").unwrap();
+    writer.string("two").unwrap();
+    writer.begin_object().unwrap();
+    writer.number_field("three", 3).unwrap();
+    writer.end_object().unwrap();
+    writer.end_array().unwrap();
+    writer.end_object().unwrap();
+
+    assert_eq!(
+        writer.finish().as_str(),
+        r#"{"name":"value","amount":"10","list":["one","two",{"three":3}]}"#
+    );
+}
+
+#[test]
+fn test_escape_json_strings() {
+    let mut writer = JsonWriter::<128>::new();
+    writer
+        .string("\"quoted\" back\\slash\nline\ttab\u{1}")
+        .unwrap();
+
+    assert_eq!(
+        writer.finish().as_str(),
+        r#""\"quoted\" back\\slash\nline\ttab\u0001""#
+    );
+}
+
+#[test]
+fn test_json_capacity() {
+    let mut writer = JsonWriter::<8>::new();
+
+    assert_eq!(writer.string("123456"), Ok(()));
+    assert_eq!(writer.raw("1"), Err(ContractError::JsonCapacityExceeded));
+    assert_eq!(
+        writer.string("\""),
+        Err(ContractError::JsonCapacityExceeded)
+    );
+    assert_eq!(writer.finish().as_str(), r#""123456""#);
+
+    let mut writer = JsonWriter::<1024>::new();
+    writer.raw(&"a".repeat(513)).unwrap();
+    assert_eq!(writer.into_vec(), Err(ContractError::JsonCapacityExceeded));
+}
+
+#[test]
+fn test_read_json_field() {
+    let json = br#"{ "sender_id" : "alice.near", "nested": {"amount": "1", "list": [1, "2", {}]}, "empty": [], "flag": true, "amount": "12345", "msg": "{\"amount\":\"7\"}" }"#;
+
+    assert_eq!(
+        JsonReader::field(json, "amount").and_then(|mut r| r.read_amount()),
+        Ok(12345)
+    );
+    assert_eq!(
+        JsonReader::field(json, "msg").and_then(|mut r| r.read_str()),
+        Ok(r#"{\"amount\":\"7\"}"#)
+    );
+    assert_eq!(
+        JsonReader::field(json, "fee").map(|_| ()),
+        Err(ContractError::BadJson)
+    );
+    assert_eq!(
+        JsonReader::field(br#"{"amount":12345}"#, "amount").and_then(|mut r| r.read_amount()),
+        Err(ContractError::BadNumber)
+    );
+    assert_eq!(
+        JsonReader::field(br#"{"amount":"1""#, "msg").map(|_| ()),
+        Err(ContractError::BadJson)
+    );
+    assert_eq!(
+        JsonReader::field(b"{}", "amount").map(|_| ()),
+        Err(ContractError::BadJson)
+    );
+}
+
+#[test]
+fn test_parse_u128() {
+    assert_eq!(parse_u128(b"42"), Ok(42));
+    assert_eq!(parse_u128(br#""42""#), Ok(42));
+    assert_eq!(
+        parse_u128(br#""340282366920938463463374607431768211455""#),
+        Ok(u128::MAX)
+    );
+    assert_eq!(
+        parse_u128(br#""340282366920938463463374607431768211456""#),
+        Err(ContractError::BadNumber)
+    );
+    assert_eq!(
+        parse_u128(b"\"4\xc3\x28\""),
+        Err(ContractError::BadUtf8String)
+    );
+    assert_eq!(parse_u128(b"4x"), Err(ContractError::BadJson));
+    assert_eq!(parse_u128(br#""4x""#), Err(ContractError::BadNumber));
+    assert_eq!(parse_u128(b""), Err(ContractError::BadNumber));
+}
+
+#[test]
+fn test_parse_u128_prefix() {
+    assert_eq!(parse_u128_prefix(b"42"), Ok(42));
+    assert_eq!(parse_u128_prefix(br#""42""#), Ok(42));
+    assert_eq!(
+        parse_u128_prefix(br#""340282366920938463463374607431768211455""#),
+        Ok(u128::MAX)
+    );
+    assert_eq!(
+        parse_u128_prefix(br#""340282366920938463463374607431768211456""#),
+        Err(ContractError::BadNumber)
+    );
+    assert_eq!(
+        parse_u128_prefix(b"\"4\xc3\x28\""),
+        Err(ContractError::BadUtf8String)
+    );
+    assert_eq!(parse_u128_prefix(b"4x"), Ok(4));
+    assert_eq!(parse_u128_prefix(br#""42", "rest""#), Ok(42));
+    assert_eq!(parse_u128_prefix(br#""4x""#), Err(ContractError::BadNumber));
+    assert_eq!(parse_u128_prefix(b""), Err(ContractError::BadNumber));
+}
@@ -1,61 +1,80 @@
 #![cfg_attr(target_arch = "wasm32", no_std)]
 #![allow(clippy::module_name_repetitions, clippy::as_conversions)]
 
+use arrayvec::ArrayVec;
+#[cfg(test)]
 use borsh::BorshDeserialize;
 #[cfg(target_arch = "wasm32")]
 use core::alloc::{GlobalAlloc, Layout};
 
-use crate::error::ContractError;
+pub use crate::error::ContractError;
+use crate::events::{EventData, ForwardEvent, TargetChanged};
+use crate::gas::Stage;
 use crate::params::{
-    ft_balance_args, ft_transfer_args, ft_transfer_call_args, FeesParams, FinishForwardParams,
-    ForwardParams, State,
+    ft_balance_args, ft_transfer_args, transfer_args, AccruedFee, FeeCacheTtl, FeeRate, FeesParams,
+    FinishForwardParams, ForwardLock, ForwardManyParams, ForwardParams, ForwardReceipt, InitParams,
+    OriginalTarget, ReclaimParams, Refund, Relayers, ResolveFeeParams, ResolveForwardParams,
+    ResolveReclaimParams, TokenList, TransferGas, TransferGasParams,
 };
-use crate::runtime::{panic_utf8, Env, PromiseHandler, Runtime, SdkExpect, SdkUnwrap, IO};
+use crate::runtime::{
+    panic_utf8, Env, PromiseHandler, PromiseId, SdkExpect, SdkUnwrap, StorageIntermediate, IO,
+    MAX_COMBINED_PROMISES,
+};
+#[cfg(test)]
+use crate::runtime::{MockAction, MockPromise, MockRuntime};
+use crate::state::{CallerPolicy, State, Target, Targets, MAX_TARGETS};
 use crate::types::{
-    AccountId, PromiseAction, PromiseBatchAction, PromiseCreateArgs, PromiseResult,
+    AccountId, Address, PromiseAction, PromiseBatchAction, PromiseCreateArgs, PromiseResult,
     PromiseWithCallbackArgs, Vec,
 };
 
+mod entry;
 mod error;
+mod events;
+mod gas;
+mod json;
+mod math;
 mod params;
 mod runtime;
+mod state;
 mod types;
 
 #[cfg(target_arch = "wasm32")]
 #[global_allocator]
 static ALLOCATOR: NoopAllocator = NoopAllocator;
 
-const MINIMUM_BALANCE: u128 = 310_000_000_000_000_000_000_000;
 const ZERO_YOCTO: u128 = 0;
 
-const CALCULATE_FEES_GAS: u64 = 4_000_000_000_000;
-const NEAR_DEPOSIT_GAS: u64 = 2_000_000_000_000;
-const FT_BALANCE_GAS: u64 = 2_000_000_000_000;
-const FT_TRANSFER_GAS: u64 = 3_000_000_000_000;
-const FT_TRANSFER_CALL_GAS: u64 = 80_000_000_000_000;
-const CALCULATE_FEES_CALLBACK_GAS: u64 = 120_000_000_000_000;
-const FINISH_FORWARD_GAS: u64 = 100_000_000_000_000;
-
 // Key is used for upgrading the smart contract.
 // base58 representation of the key is: "ed25519:BaiF3VUJf5pxB9ezVtzH4SejpdYc7EA3SqrKczsj1wno";
 const UPDATER_PK: [u8; 33] = [
     0, 157, 55, 171, 39, 212, 8, 14, 19, 58, 101, 78, 158, 202, 229, 222, 152, 23, 144, 112, 79,
     136, 229, 203, 142, 41, 95, 170, 31, 58, 47, 213, 152,
 ];
+// The fees contract method which takes the fee of the tokens forwarded to a single target.
+const CALCULATE_FEES_METHOD: &str = "calculate_fees";
+// The fees contract method which takes the split of the forwarded tokens between the targets.
+// Only the upgraded fees contract provides it.
+const CALCULATE_SPLIT_FEES_METHOD: &str = "calculate_split_fees";
+const FEE_RATE_METHOD: &str = "get_fee_rate";
 // In case we get near as a token id it means we need to transfer native NEAR tokens.
 const NEAR: &str = "near";
 
-#[no_mangle]
-pub extern "C" fn new() {
-    let mut io = Runtime;
-
+pub fn new<I: IO + Env + PromiseHandler>(mut io: I) {
     if State::load(&io).is_some() {
-        panic_utf8(b"ERR_ALREADY_INITIALIZED");
+        panic_utf8(ContractError::AlreadyInitialized.as_ref());
     }
 
-    let state: State = io.read_input_borsh().sdk_unwrap();
+    let InitParams {
+        state,
+        add_full_access_key,
+    } = io.read_input_borsh().sdk_unwrap();
     state.save(&mut io);
 
+    if !add_full_access_key {
+        return;
+    }
+
     let current_account_id = io.current_account_id();
     let promise = PromiseBatchAction {
         target_account_id: current_account_id,
@@ -69,145 +88,525 @@ pub extern "C" fn new() {
     io.promise_return(promise_id);
 }
 
-#[no_mangle]
-pub extern "C" fn forward() {
-    let io = Runtime;
+/// Deploys the new code of the forwarder passed as the raw input and migrates the state to
+/// the layout of that code.
+pub fn upgrade<I: IO + Env + PromiseHandler>(mut io: I) {
+    owner_state(&io);
+
+    let input = io.read_input();
+    let len = input.len();
+
+    if len == 0 || len > runtime::MAX_REGISTER_LEN {
+        panic_utf8(ContractError::InvalidCodeSize.as_ref());
+    }
+
+    let code = runtime::scratch_buffer(len).sdk_unwrap();
+    input.copy_to_slice(code);
+
+    let promise = PromiseBatchAction {
+        target_account_id: io.current_account_id(),
+        actions: [
+            PromiseAction::DeployContract { code },
+            PromiseAction::FunctionCall {
+                method: "migrate",
+                args: &[],
+                attached_balance: ZERO_YOCTO,
+                gas: gas::MIGRATE_GAS,
+            },
+        ],
+    };
+
+    let promise_id = unsafe { io.promise_create_batch(&promise) };
+    io.promise_return(promise_id);
+}
+
+/// Upgrades the stored state to the layout of the current code. It should be called after
+/// every update of the contract code.
+pub fn migrate<I: IO + Env + PromiseHandler>(mut io: I) {
+    let state = State::load(&io).sdk_expect(ContractError::NotInitialized);
+    let predecessor_id = io.predecessor_account_id();
+
+    if !state.is_owner(predecessor_id) && predecessor_id != io.current_account_id() {
+        panic_utf8(ContractError::NotOwner.as_ref());
+    }
+
+    state.save(&mut io);
+}
 
+pub fn forward<I: IO + Env + PromiseHandler>(mut io: I) {
     io.assert_one_yocto().sdk_unwrap();
+    assert_caller_allowed(&io);
 
     let params: ForwardParams = io.read_input_borsh().sdk_unwrap();
+    let gas = io.remaining_gas().saturating_sub(gas::EXECUTION_GAS);
+    let promise_id = forward_token(&mut io, &params, gas);
 
-    if params.token_id.as_str() == NEAR {
-        forward_native_token(io);
-    } else {
-        forward_nep141_token(io, params.token_id);
+    io.promise_return(promise_id);
+}
+
+/// Forwards the whole balances of several tokens at once. The attached gas should be enough to
+/// cover the forwarding of every token in the list.
+///
+/// The gas is split between the tokens by the gas their forwarding needs. The forwardings are
+/// independent, so nothing is returned, because NEAR can't return the joint promise.
+pub fn forward_many<I: IO + Env + PromiseHandler>(mut io: I) {
+    io.assert_one_yocto().sdk_unwrap();
+    assert_caller_allowed(&io);
+
+    let ForwardManyParams { token_ids } = io.read_input_borsh().sdk_unwrap();
+
+    if token_ids.is_empty() {
+        panic_utf8(ContractError::EmptyTokenList.as_ref());
+    }
+
+    let state = State::load(&io).sdk_expect(ContractError::NotInitialized);
+    let stages = token_ids
+        .iter()
+        .map(|token_id| forward_stage(&io, &state, token_id))
+        .collect::<ArrayVec<_, MAX_COMBINED_PROMISES>>();
+    let total = gas::total(&stages);
+    let available = io
+        .remaining_gas()
+        .saturating_sub(gas::EXECUTION_GAS.saturating_mul(stages.len() as u64));
+
+    if available < total.min {
+        panic_utf8(ContractError::NotEnoughGas.as_ref());
+    }
+
+    for (token_id, stage) in token_ids.into_iter().zip(stages) {
+        forward_token(
+            &mut io,
+            &ForwardParams {
+                token_id,
+                amount: None,
+            },
+            gas::share(available, stage, total),
+        );
     }
 }
 
-#[no_mangle]
-pub extern "C" fn calculate_fees_callback() {
-    let mut io = Runtime;
+pub fn calculate_fees_callback<I: IO + Env + PromiseHandler>(mut io: I) {
     io.assert_private_call().sdk_unwrap();
 
     let params: ForwardParams = io.read_input_borsh().sdk_unwrap();
-    let state = State::load(&io).sdk_expect("No state");
-    let amount: u128 = match io.promise_result(0).sdk_expect("No promise result") {
-        PromiseResult::Successful(v) => params::vec_to_number(&v).sdk_unwrap(),
-        _ => panic_utf8(b"FEE RESULT IS NOT READY"),
+    let state = State::load(&io).sdk_expect(ContractError::NotInitialized);
+    let balance: u128 = match io
+        .promise_result(0)
+        .sdk_expect(ContractError::NoPromiseResult)
+        .parse(json::parse_u128, json::parse_u128_prefix)
+    {
+        Ok(balance) => balance,
+        Err(e) => {
+            return forward_failed(&mut io, EventData::new(&state, &params.token_id), e);
+        }
     };
+    // The accrued fee is held for the fees contract, so it isn't forwarded.
+    let balance = balance.saturating_sub(AccruedFee::load(&io, &params.token_id));
+    let amount = match params.amount {
+        Some(amount) if amount > balance => {
+            return forward_failed(
+                &mut io,
+                EventData::new(&state, &params.token_id).amount(amount),
+                ContractError::AmountExceedsBalance,
+            )
+        }
+        Some(amount) => amount,
+        None => balance,
+    };
+    // The refunded tokens which are forwarded again are no longer recorded as refunded.
+    let refunded = Refund::load(&io, &params.token_id).min(balance - amount);
+    Refund::save(&mut io, &params.token_id, refunded);
 
-    let promise_id = unsafe {
-        let promise_id = io.promise_create_call(&PromiseCreateArgs {
-            target_account_id: state.fees_contract_id,
-            method: "calculate_fees",
-            args: types::to_borsh(&FeesParams {
-                amount,
-                token_id: &params.token_id,
-                target_network: &state.target_network,
-                target_address: state.target_address,
-            })
-            .sdk_unwrap(),
-            attached_balance: ZERO_YOCTO,
-            attached_gas: CALCULATE_FEES_GAS,
-        });
+    // The gas attached to the callback is the gas left for the rest of the forwarding.
+    let gas = io.remaining_gas().saturating_sub(gas::EXECUTION_GAS);
+    // The fee is calculated with the cached rate, so the fees contract isn't called.
+    let result = match fee_rate(&mut io, &params.token_id) {
+        Some(rate) => finish_forward(
+            &mut io,
+            &state,
+            params.token_id,
+            amount,
+            rate.fee(amount),
+            gas,
+        ),
+        None => calculate_fees(&mut io, &state, params.token_id, amount, gas),
+    };
 
-        io.promise_attach_callback(
-            promise_id,
-            &PromiseCreateArgs {
-                target_account_id: io.current_account_id(),
-                method: "finish_forward_callback",
-                args: types::to_borsh(&FinishForwardParams {
-                    amount,
-                    token_id: params.token_id,
-                    promise_idx: 0,
-                })
-                .sdk_unwrap(),
-                attached_balance: 2,
-                attached_gas: FINISH_FORWARD_GAS,
-            },
-        )
+    match result {
+        Ok(promise_id) => io.promise_return(promise_id),
+        Err(e) => forward_failed(
+            &mut io,
+            EventData::new(&state, &params.token_id).amount(amount),
+            e,
+        ),
+    }
+}
+
+/// Returns the fee rate of the token read from the fees contract along with the balance, or the
+/// cached one. The fees contract is called to calculate the fee if the rate couldn't be read,
+/// e.g. because the fees contract doesn't provide it.
+fn fee_rate<I: IO + Env + PromiseHandler>(io: &mut I, token_id: &AccountId) -> Option<FeeRate> {
+    if io.promise_results_count() > 1 {
+        let rate_bps = io.promise_result(1)?.parse_borsh::<u64>().ok()?;
+
+        return Some(FeeRate::save(io, token_id, rate_bps));
+    }
+
+    FeeRate::load(io, token_id)
+}
+
+/// Implementation of the NEP-141 receiver. The deposited tokens are forwarded right away, so
+/// the call should have enough gas attached to cover the whole forwarding.
+///
+/// Otherwise the call fails, and the tokens are refunded by the token contract.
+///
+/// The deposit is accepted but isn't forwarded if only the owner or the relayers could start the
+/// forwarding. The tokens are forwarded later by `forward`.
+pub fn ft_on_transfer<I: IO + Env + PromiseHandler>(mut io: I) {
+    let token_id = io.predecessor_account_id();
+    let amount =
+        params::ft_on_transfer_amount(&io.read_input().to_prefix_vec().sdk_unwrap()).sdk_unwrap();
+
+    // Return the whole amount to the sender if the forwarder is not initialized, the token isn't
+    // allowed or another forwarding of the token is in progress.
+    let state = match State::load(&io) {
+        Some(state)
+            if TokenList::is_allowed(&io, &token_id) && !ForwardLock::is_locked(&io, &token_id) =>
+        {
+            state
+        }
+        _ => {
+            let refund = params::json_string(params::amount_to_str(amount).as_str()).sdk_unwrap();
+            io.return_output(refund.as_bytes());
+            return;
+        }
     };
 
-    io.promise_return(promise_id);
+    if amount > 0 && state.caller_policy == CallerPolicy::Anyone {
+        ForwardLock::lock(&mut io, &token_id);
+        ForwardEvent::Started.emit(&EventData::new(&state, &token_id).amount(amount));
+        let gas = io.remaining_gas().saturating_sub(gas::EXECUTION_GAS);
+        // The forwarding isn't returned from the method, because its result would be treated by
+        // the token contract as the amount of tokens to refund.
+        let _ = match FeeRate::load(&io, &token_id) {
+            Some(rate) => finish_forward(&mut io, &state, token_id, amount, rate.fee(amount), gas),
+            None => calculate_fees(&mut io, &state, token_id, amount, gas),
+        }
+        .sdk_unwrap();
+    }
+
+    io.return_output(params::json_string("0").sdk_unwrap().as_bytes());
 }
 
-#[no_mangle]
-pub extern "C" fn finish_forward_callback() {
-    let mut io = Runtime;
+pub fn finish_forward_callback<I: IO + Env + PromiseHandler>(mut io: I) {
     io.assert_private_call().sdk_unwrap();
 
     let params: FinishForwardParams = io.read_input_borsh().sdk_unwrap();
-    let state = State::load(&io).sdk_expect("No state");
+    let state = State::load(&io).sdk_expect(ContractError::NotInitialized);
+    let data = EventData::new(&state, &params.token_id).amount(params.amount);
+
+    // The results before the fee are the deposits of native NEAR as wNEAR. The forwarder doesn't
+    // hold the wNEAR if the deposit failed, so nothing is transferred or recorded as refunded.
+    let deposited = (0..params.promise_idx).all(|i| {
+        matches!(
+            io.promise_result(i),
+            Some(PromiseResult::Successful(_) | PromiseResult::TooLarge(_))
+        )
+    });
+
+    if !deposited {
+        return forward_failed(&mut io, data, ContractError::PromiseFailed);
+    }
+
     let fee: u128 = match io
         .promise_result(params.promise_idx)
-        .sdk_expect("No promise result")
+        .sdk_expect(ContractError::NoPromiseResult)
+        .parse_borsh()
     {
-        PromiseResult::Successful(v) => u128::try_from_slice(&v)
-            .map_err(|_| ContractError::BorshDeserializeError)
-            .sdk_unwrap(),
-        _ => panic_utf8(b"FEE RESULT IS NOT READY"),
+        Ok(fee) => fee,
+        Err(e) => return forward_failed(&mut io, data, e),
     };
 
-    let amount = params.amount.saturating_sub(fee);
+    let gas = io.remaining_gas().saturating_sub(gas::EXECUTION_GAS);
+
+    match finish_forward(&mut io, &state, params.token_id, params.amount, fee, gas) {
+        Ok(promise_id) => io.promise_return(promise_id),
+        Err(e) => forward_failed(&mut io, data.fee(fee), e),
+    }
+}
+
+pub fn resolve_forward_callback<I: IO + Env + PromiseHandler>(mut io: I) {
+    io.assert_private_call().sdk_unwrap();
+
+    let params: ResolveForwardParams = io.read_input_borsh().sdk_unwrap();
 
-    let mut promise_id = unsafe {
-        io.promise_create_call(&PromiseCreateArgs {
-            target_account_id: params.token_id,
-            method: "ft_transfer_call",
-            args: ft_transfer_call_args(&state.target_network, amount, state.target_address),
-            attached_balance: 1,
-            attached_gas: FT_TRANSFER_CALL_GAS,
+    let sent = params.sent.iter().map(|(_, amount)| amount).sum::<u128>();
+    // The result of `ft_transfer_call` is the amount used by the receiver. The rest of the sent
+    // tokens have been refunded to the forwarder. The result of `ft_transfer` is empty, because
+    // the whole amount is delivered to the NEAR account.
+    let transfers = params
+        .sent
+        .iter()
+        .enumerate()
+        .map(|(i, (idx, amount))| {
+            let used = match io
+                .promise_result(i as u64)
+                .sdk_expect(ContractError::NoPromiseResult)
+            {
+                PromiseResult::Successful(v) if v.is_empty() => *amount,
+                result => result
+                    .parse(json::parse_u128, json::parse_u128_prefix)
+                    .unwrap_or_default()
+                    .min(*amount),
+            };
+            (*idx, used)
         })
+        .collect::<ArrayVec<_, MAX_TARGETS>>();
+    let used = transfers.iter().map(|(_, used)| used).sum::<u128>();
+    // The fee is charged proportionally to the amount that was actually delivered.
+    let fee = math::mul_div(params.fee, used, sent).unwrap_or_default();
+    let remainder = params.amount - used - fee;
+
+    let state = State::load(&io).sdk_expect(ContractError::NotInitialized);
+
+    if used == 0 {
+        ForwardEvent::Failed.emit(
+            &EventData::new(&state, &params.token_id)
+                .amount(sent)
+                .reason(ContractError::TransferRefunded.as_ref()),
+        );
+    }
+
+    if remainder > 0 {
+        let refunded = Refund::load(&io, &params.token_id).saturating_add(remainder);
+        Refund::save(&mut io, &params.token_id, refunded);
+    }
+
+    let receipt = ForwardReceipt {
+        token_id: params.token_id,
+        amount: params.amount,
+        fee,
+        delivered: used,
+        transfers: transfers.into(),
+        refunded: remainder,
+        transfer_succeeded: used == sent,
+        fee_succeeded: true,
     };
 
+    // The token stays locked until the fee is transferred, so the next forwarding can't read the
+    // balance which still includes the fee. The lock is released and the receipt is returned by
+    // `resolve_fee_callback` once the result of the fee transfer is known.
     if fee > 0 {
-        promise_id = unsafe {
-            io.promise_attach_callback(
-                promise_id,
-                &PromiseCreateArgs {
-                    target_account_id: params.token_id,
-                    method: "ft_transfer",
-                    args: ft_transfer_args(&state.fees_contract_id, fee),
-                    attached_balance: 1,
-                    attached_gas: FT_TRANSFER_GAS,
-                },
-            )
+        let promise_id = transfer_fee(&mut io, &state, params.token_id, fee, Some(receipt));
+        io.promise_return(promise_id);
+    } else {
+        ForwardLock::unlock(&mut io, &receipt.token_id);
+        return_receipt(&mut io, &state, &receipt);
+    }
+}
+
+/// Records the fee as accrued if its transfer to the fees contract failed.
+///
+/// The forwarding which charged the fee is finished here: the token is unlocked and its receipt
+/// is returned with the result of the transfer.
+pub fn resolve_fee_callback<I: IO + Env + PromiseHandler>(mut io: I) {
+    io.assert_private_call().sdk_unwrap();
+
+    let params: ResolveFeeParams = io.read_input_borsh().sdk_unwrap();
+    let succeeded = matches!(
+        io.promise_result(0)
+            .sdk_expect(ContractError::NoPromiseResult),
+        PromiseResult::Successful(_) | PromiseResult::TooLarge(_)
+    );
+
+    if !succeeded {
+        let accrued = AccruedFee::load(&io, &params.token_id).saturating_add(params.fee);
+        AccruedFee::save(&mut io, &params.token_id, accrued);
+    }
+
+    if let Some(receipt) = params.receipt {
+        ForwardLock::unlock(&mut io, &params.token_id);
+        let state = State::load(&io).sdk_expect(ContractError::NotInitialized);
+        let receipt = ForwardReceipt {
+            fee_succeeded: succeeded,
+            ..receipt
         };
+        return_receipt(&mut io, &state, &receipt);
+    }
+}
+
+/// Returns the receipt of the forwarding as JSON. The receipt which doesn't fit the buffer isn't
+/// returned rather than failing the callback.
+fn return_receipt<I: IO>(io: &mut I, state: &State, receipt: &ForwardReceipt) {
+    if let Ok(json) = params::receipt_json(receipt, &state.target) {
+        io.return_output(json.as_bytes());
+    }
+}
+
+/// Transfers the accrued fee of the token passed as the borsh input to the fees contract. It
+/// could be called by the fees contract only.
+pub fn claim_fees<I: IO + Env + PromiseHandler>(mut io: I) {
+    let state = State::load(&io).sdk_expect(ContractError::NotInitialized);
+
+    if io.predecessor_account_id() != state.fees_contract_id {
+        panic_utf8(ContractError::NotFeesContract.as_ref());
+    }
+
+    let token_id: AccountId = io.read_input_borsh().sdk_unwrap();
+    let fee = AccruedFee::load(&io, &token_id);
+
+    if fee == 0 {
+        panic_utf8(ContractError::NothingToClaim.as_ref());
     }
 
+    AccruedFee::save(&mut io, &token_id, 0);
+
+    let promise_id = transfer_fee(&mut io, &state, token_id, fee, None);
     io.promise_return(promise_id);
 }
 
-fn forward_native_token<I: IO + Env + PromiseHandler>(mut io: I) {
-    let amount = io
+/// Transfers the fee to the fees contract. The fee is accrued if the transfer fails.
+fn transfer_fee<I: IO + Env + PromiseHandler>(
+    io: &mut I,
+    state: &State,
+    token_id: AccountId,
+    fee: u128,
+    receipt: Option<ForwardReceipt>,
+) -> PromiseId {
+    unsafe {
+        io.promise_create_with_callback(&PromiseWithCallbackArgs {
+            base: PromiseCreateArgs {
+                target_account_id: token_id,
+                method: "ft_transfer",
+                args: ft_transfer_args(&state.fees_contract_id, fee).sdk_unwrap(),
+                attached_balance: 1,
+                attached_gas: gas::FT_TRANSFER_GAS,
+            },
+            callback: PromiseCreateArgs {
+                target_account_id: io.current_account_id(),
+                method: "resolve_fee_callback",
+                args: types::to_borsh(&ResolveFeeParams {
+                    token_id,
+                    fee,
+                    receipt,
+                })
+                .sdk_unwrap(),
+                attached_balance: ZERO_YOCTO,
+                attached_gas: gas::RESOLVE_FEE_GAS,
+            },
+        })
+    }
+}
+
+/// The whole forwarding of the token started by `forward`.
+fn forward_stage<I: IO + Env>(io: &I, state: &State, token_id: &AccountId) -> Stage {
+    let count = state.target.count();
+
+    if token_id.as_str() == NEAR {
+        let transfer_gas = TransferGas::load(io, &state.wnear_contract_id);
+        gas::total(&gas::forward_native(transfer_gas, count))
+    } else if FeeRate::needs_refresh(io, token_id) {
+        gas::total(&gas::forward_nep141_with_fee_rate(
+            TransferGas::load(io, token_id),
+            count,
+        ))
+    } else {
+        gas::total(&gas::forward_nep141(TransferGas::load(io, token_id), count))
+    }
+}
+
+/// Checks that the predecessor could start the forwarding according to the caller policy. The
+/// owner could always start it.
+fn assert_caller_allowed<I: IO + Env>(io: &I) {
+    let state = State::load(io).sdk_expect(ContractError::NotInitialized);
+    let caller = io.predecessor_account_id();
+    let allowed = state.is_owner(caller)
+        || match state.caller_policy {
+            CallerPolicy::Anyone => true,
+            CallerPolicy::Owner => false,
+            CallerPolicy::Relayers => Relayers::contains(io, &caller),
+        };
+
+    if !allowed {
+        panic_utf8(ContractError::CallerNotAllowed.as_ref());
+    }
+}
+
+fn forward_token<I: IO + Env + PromiseHandler>(
+    io: &mut I,
+    params: &ForwardParams,
+    gas: u64,
+) -> PromiseId {
+    let state = State::load(io).sdk_expect(ContractError::NotInitialized);
+    // Native NEAR is forwarded as wNEAR, so it shares the lock and the token lists with wNEAR.
+    let locked_token_id = if params.token_id.as_str() == NEAR {
+        state.wnear_contract_id
+    } else {
+        params.token_id
+    };
+
+    if !TokenList::is_allowed(io, &locked_token_id) {
+        panic_utf8(ContractError::TokenNotAllowed.as_ref());
+    }
+
+    if ForwardLock::is_locked(io, &locked_token_id) {
+        panic_utf8(ContractError::ForwardInProgress.as_ref());
+    }
+
+    ForwardLock::lock(io, &locked_token_id);
+
+    if params.token_id.as_str() == NEAR {
+        forward_native_token(io, &state, params, gas)
+    } else {
+        forward_nep141_token(io, &state, params, gas)
+    }
+}
+
+fn forward_native_token<I: IO + Env + PromiseHandler>(
+    io: &mut I,
+    state: &State,
+    params: &ForwardParams,
+    gas: u64,
+) -> PromiseId {
+    let transfer_gas = TransferGas::load(io, &state.wnear_contract_id);
+    let [near_deposit_gas, calculate_fees_gas, finish_forward_gas] =
+        gas::split(gas, gas::forward_native(transfer_gas, state.target.count())).sdk_unwrap();
+    let available = io
         .account_balance()
-        .checked_sub(MINIMUM_BALANCE)
+        .checked_sub(state.native_reserve(io.storage_usage()))
         .filter(|a| *a > 0)
-        .expect("Too low balance");
+        .ok_or(ContractError::InsufficientBalance)
+        .sdk_unwrap();
+    let amount = match params.amount {
+        Some(amount) if amount > available => {
+            panic_utf8(ContractError::AmountExceedsBalance.as_ref())
+        }
+        Some(amount) => amount,
+        None => available,
+    };
 
-    let state = State::load(&io).unwrap();
+    ForwardEvent::Started.emit(&EventData::new(state, &params.token_id).amount(amount));
 
-    let promise_id = unsafe {
+    unsafe {
         let promise_id = io.promise_create_and_combine(&[
             PromiseCreateArgs {
                 target_account_id: state.wnear_contract_id,
                 method: "near_deposit",
                 args: Vec::new(),
                 attached_balance: amount,
-                attached_gas: NEAR_DEPOSIT_GAS,
+                attached_gas: near_deposit_gas,
             },
             PromiseCreateArgs {
                 target_account_id: state.fees_contract_id,
-                method: "calculate_fees",
+                method: calculate_fees_method(&state.target),
                 args: types::to_borsh(&FeesParams {
                     amount,
                     token_id: &state.wnear_contract_id,
-                    target_network: &state.target_network,
-                    target_address: state.target_address,
+                    target: &state.target,
                 })
                 .sdk_unwrap(),
                 attached_balance: ZERO_YOCTO,
-                attached_gas: CALCULATE_FEES_GAS,
+                attached_gas: calculate_fees_gas,
             },
         ]);
 
@@ -223,31 +622,253 @@ fn forward_native_token<I: IO + Env + PromiseHandler>(mut io: I) {
                 })
                 .sdk_unwrap(),
                 attached_balance: 2,
-                attached_gas: FINISH_FORWARD_GAS,
+                attached_gas: finish_forward_gas,
             },
         )
+    }
+}
+
+fn forward_nep141_token<I: IO + Env + PromiseHandler>(
+    io: &mut I,
+    state: &State,
+    params: &ForwardParams,
+    gas: u64,
+) -> PromiseId {
+    let token_id = params.token_id;
+    let transfer_gas = TransferGas::load(io, &token_id);
+    // The expired fee rate is read again along with the balance.
+    let refresh_fee_rate = FeeRate::needs_refresh(io, &token_id);
+    let count = state.target.count();
+    let [ft_balance_gas, fee_rate_gas, callback_gas] = if refresh_fee_rate {
+        gas::split(gas, gas::forward_nep141_with_fee_rate(transfer_gas, count))
+    } else {
+        gas::split(gas, gas::forward_nep141(transfer_gas, count))
+            .map(|[ft_balance, callback]| [ft_balance, 0, callback])
+    }
+    .sdk_unwrap();
+    let mut event = EventData::new(state, &token_id);
+
+    if let Some(amount) = params.amount {
+        event = event.amount(amount);
+    }
+
+    ForwardEvent::Started.emit(&event);
+
+    let ft_balance = PromiseCreateArgs {
+        target_account_id: token_id,
+        method: "ft_balance_of",
+        args: ft_balance_args(&io.current_account_id()).sdk_unwrap(),
+        attached_balance: ZERO_YOCTO,
+        attached_gas: ft_balance_gas,
+    };
+    let callback = PromiseCreateArgs {
+        target_account_id: io.current_account_id(),
+        method: "calculate_fees_callback",
+        args: types::to_borsh(params).sdk_unwrap(),
+        attached_balance: ZERO_YOCTO,
+        attached_gas: callback_gas,
     };
 
-    io.promise_return(promise_id);
+    unsafe {
+        let promise_id = if refresh_fee_rate {
+            io.promise_create_and_combine(&[
+                ft_balance,
+                PromiseCreateArgs {
+                    target_account_id: state.fees_contract_id,
+                    method: FEE_RATE_METHOD,
+                    args: types::to_borsh(&token_id).sdk_unwrap(),
+                    attached_balance: ZERO_YOCTO,
+                    attached_gas: fee_rate_gas,
+                },
+            ])
+        } else {
+            io.promise_create_call(&ft_balance)
+        };
+
+        io.promise_attach_callback(promise_id, &callback)
+    }
+}
+
+/// Checks the fee and transfers the rest of the amount to the targets with the gas left for the
+/// forwarding. The fee is transferred to the fees contract once the transfers are resolved.
+fn finish_forward<I: IO + Env + PromiseHandler>(
+    io: &mut I,
+    state: &State,
+    token_id: AccountId,
+    amount: u128,
+    fee: u128,
+    gas: u64,
+) -> Result<PromiseId, ContractError> {
+    // The fee is checked before any transfer, so the tokens stay on the forwarder if the fees
+    // contract quotes too much.
+    state.check_fee(amount, fee)?;
+
+    ForwardEvent::FeeQuoted.emit(&EventData::new(state, &token_id).amount(amount).fee(fee));
+
+    let forwarded = amount - fee;
+    // The targets which get nothing are skipped. The first target gets the rounding dust, so
+    // it's skipped only if there is nothing to forward at all.
+    let transfers = (0u8..)
+        .zip(state.target.split(forwarded))
+        .filter(|(i, amount)| *i == 0 || *amount > 0)
+        .collect::<ArrayVec<_, MAX_TARGETS>>();
+    let stages = [
+        gas::transfers(TransferGas::load(io, &token_id), transfers.len()),
+        gas::RESOLVE_FORWARD,
+    ];
+    let [total_transfer_gas, resolve_gas] = gas::split(gas, stages)?;
+    // The minimum gas of the stage covers the transfer gas of every transfer.
+    let transfer_gas = total_transfer_gas / transfers.len() as u64;
+    let transfer_args = transfers
+        .iter()
+        .map(|(i, amount)| transfer_args(state, usize::from(*i), *amount))
+        .collect::<Result<ArrayVec<_, MAX_TARGETS>, _>>()?;
+
+    let promise_id = unsafe {
+        let promise_ids = transfer_args
+            .into_iter()
+            .map(|(method, args)| {
+                io.promise_create_call(&PromiseCreateArgs {
+                    target_account_id: token_id,
+                    method,
+                    args,
+                    attached_balance: 1,
+                    attached_gas: transfer_gas,
+                })
+            })
+            .collect::<ArrayVec<_, MAX_TARGETS>>();
+        let promise_id = io.promise_and(&promise_ids);
+
+        io.promise_attach_callback(
+            promise_id,
+            &PromiseCreateArgs {
+                target_account_id: io.current_account_id(),
+                method: "resolve_forward_callback",
+                args: types::to_borsh(&ResolveForwardParams {
+                    amount,
+                    fee,
+                    token_id,
+                    sent: transfers.clone().into(),
+                })
+                .sdk_unwrap(),
+                attached_balance: 1,
+                attached_gas: resolve_gas,
+            },
+        )
+    };
+
+    // Every transfer is reported with the address it's sent to.
+    for (i, amount) in transfers {
+        let receiver = state.target.receiver(usize::from(i));
+        let data = EventData {
+            target: &receiver,
+            ..EventData::new(state, &token_id)
+        };
+        ForwardEvent::TransferSent.emit(&data.amount(amount).fee(fee));
+    }
+
+    Ok(promise_id)
+}
+
+fn calculate_fees<I: IO + Env + PromiseHandler>(
+    io: &mut I,
+    state: &State,
+    token_id: AccountId,
+    amount: u128,
+    gas: u64,
+) -> Result<PromiseId, ContractError> {
+    let [calculate_fees_gas, finish_forward_gas] = gas::split(
+        gas,
+        gas::calculate_fees(TransferGas::load(io, &token_id), state.target.count()),
+    )?;
+
+    let promise_id = unsafe {
+        let promise_id = io.promise_create_call(&PromiseCreateArgs {
+            target_account_id: state.fees_contract_id,
+            method: calculate_fees_method(&state.target),
+            args: types::to_borsh(&FeesParams {
+                amount,
+                token_id: &token_id,
+                target: &state.target,
+            })
+            .sdk_unwrap(),
+            attached_balance: ZERO_YOCTO,
+            attached_gas: calculate_fees_gas,
+        });
+
+        io.promise_attach_callback(
+            promise_id,
+            &PromiseCreateArgs {
+                target_account_id: io.current_account_id(),
+                method: "finish_forward_callback",
+                args: types::to_borsh(&FinishForwardParams {
+                    amount,
+                    token_id,
+                    promise_idx: 0,
+                })
+                .sdk_unwrap(),
+                attached_balance: 2,
+                attached_gas: finish_forward_gas,
+            },
+        )
+    };
+
+    Ok(promise_id)
 }
 
-fn forward_nep141_token<I: IO + Env + PromiseHandler>(mut io: I, token_id: AccountId) {
-    let callback_args = types::to_borsh(&token_id).sdk_unwrap();
+/// Returns the fees contract method which takes the fee of the tokens forwarded to the target.
+/// The tokens sent to a single target are passed to the method every fees contract provides.
+fn calculate_fees_method(target: &Target) -> &'static str {
+    if target.is_split() {
+        CALCULATE_SPLIT_FEES_METHOD
+    } else {
+        CALCULATE_FEES_METHOD
+    }
+}
+
+/// Finishes the forwarding which failed in a callback. The callback doesn't panic, because the
+/// panic would revert the release of the lock.
+fn forward_failed<I: IO>(io: &mut I, data: EventData, error: ContractError) {
+    ForwardLock::unlock(io, data.token_id);
+    ForwardEvent::Failed.emit(&data.reason(error.as_ref()));
+}
+
+/// Transfers the refunded tokens recorded for the token to the receiver. The owner of the
+/// forwarders created by the factory is the factory, so the receiver is passed explicitly.
+///
+/// The record is restored by `resolve_reclaim_callback` if the transfer fails.
+pub fn reclaim<I: IO + Env + PromiseHandler>(mut io: I) {
+    owner_state(&io);
+
+    let ReclaimParams {
+        token_id,
+        receiver_id,
+    } = io.read_input_borsh().sdk_unwrap();
+    let amount = Refund::load(&io, &token_id);
+
+    if amount == 0 {
+        panic_utf8(ContractError::NothingToReclaim.as_ref());
+    }
+
+    // The record is cleared while the transfer is in progress, so the same tokens can't be
+    // reclaimed twice.
+    Refund::save(&mut io, &token_id, 0);
+
     let promise_id = unsafe {
         io.promise_create_with_callback(&PromiseWithCallbackArgs {
             base: PromiseCreateArgs {
                 target_account_id: token_id,
-                method: "ft_balance_of",
-                args: ft_balance_args(&io.current_account_id()),
-                attached_balance: ZERO_YOCTO,
-                attached_gas: FT_BALANCE_GAS,
+                method: "ft_transfer",
+                args: ft_transfer_args(&receiver_id, amount).sdk_unwrap(),
+                attached_balance: 1,
+                attached_gas: gas::FT_TRANSFER_GAS,
             },
             callback: PromiseCreateArgs {
                 target_account_id: io.current_account_id(),
-                method: "calculate_fees_callback",
-                args: callback_args,
+                method: "resolve_reclaim_callback",
+                args: types::to_borsh(&ResolveReclaimParams { token_id, amount }).sdk_unwrap(),
                 attached_balance: ZERO_YOCTO,
-                attached_gas: CALCULATE_FEES_CALLBACK_GAS,
+                attached_gas: gas::RESOLVE_RECLAIM_GAS,
             },
         })
     };
@@ -255,26 +876,1439 @@ fn forward_nep141_token<I: IO + Env + PromiseHandler>(mut io: I, token_id: Accou
     io.promise_return(promise_id);
 }
 
-#[no_mangle]
-pub extern "C" fn destroy() {
-    let mut io = Runtime;
-    let state = State::load(&io).sdk_expect("No state");
-    let predecessor_id = io.predecessor_account_id();
+/// Records the reclaimed tokens as refunded again if their transfer failed, e.g. because the
+/// receiver isn't registered on the token.
+pub fn resolve_reclaim_callback<I: IO + Env + PromiseHandler>(mut io: I) {
+    io.assert_private_call().sdk_unwrap();
+
+    let params: ResolveReclaimParams = io.read_input_borsh().sdk_unwrap();
 
-    if !state.is_owner(predecessor_id) {
-        panic_utf8(b"ONLY OWNER CAN DESTROY");
+    if matches!(
+        io.promise_result(0)
+            .sdk_expect(ContractError::NoPromiseResult),
+        PromiseResult::Failed | PromiseResult::NotReady
+    ) {
+        let refunded = Refund::load(&io, &params.token_id).saturating_add(params.amount);
+        Refund::save(&mut io, &params.token_id, refunded);
     }
+}
 
-    let current_account_id = io.current_account_id();
-    let promise = PromiseBatchAction {
-        target_account_id: current_account_id,
-        actions: [PromiseAction::DeleteAccount {
-            beneficiary_id: predecessor_id,
-        }],
-    };
+/// Allows forwarding the token passed as the borsh input. Only the allowed tokens are forwarded
+/// once any token is allowed.
+pub fn allow_token<I: IO + Env + PromiseHandler>(mut io: I) {
+    let token_id = read_owner_token_input(&io);
+    TokenList::allow(&mut io, &token_id);
+}
 
-    let promise_id = unsafe { io.promise_create_batch(&promise) };
-    io.promise_return(promise_id);
+/// Denies forwarding the token passed as the borsh input.
+pub fn deny_token<I: IO + Env + PromiseHandler>(mut io: I) {
+    let token_id = read_owner_token_input(&io);
+    TokenList::deny(&mut io, &token_id);
+}
+
+/// Removes the token passed as the borsh input from both the allowlist and the denylist.
+pub fn remove_token<I: IO + Env + PromiseHandler>(mut io: I) {
+    let token_id = read_owner_token_input(&io);
+    TokenList::remove(&mut io, &token_id);
+}
+
+/// Overrides the minimum gas of every transfer of the token to the targets. The default gas is
+/// used again if the gas is omitted.
+///
+/// The forwarding to several targets needs the gas multiplied by their number.
+pub fn set_transfer_gas<I: IO + Env + PromiseHandler>(mut io: I) {
+    owner_state(&io);
+
+    let TransferGasParams { token_id, gas } = io.read_input_borsh().sdk_unwrap();
+    TransferGas::save(&mut io, &token_id, gas);
+}
+
+/// Changes the accounts which could start the forwarding with `forward` and `forward_many`.
+pub fn set_caller_policy<I: IO + Env + PromiseHandler>(mut io: I) {
+    let mut state = owner_state(&io);
+
+    state.caller_policy = io.read_input_borsh().sdk_unwrap();
+    state.save(&mut io);
+}
+
+/// Adds the relayer which could start the forwarding if the caller policy is `Relayers`.
+pub fn add_relayer<I: IO + Env + PromiseHandler>(mut io: I) {
+    let account_id = read_owner_token_input(&io);
+    Relayers::add(&mut io, &account_id);
+}
+
+pub fn remove_relayer<I: IO + Env + PromiseHandler>(mut io: I) {
+    let account_id = read_owner_token_input(&io);
+    Relayers::remove(&mut io, &account_id);
+}
+
+/// Sets the number of blocks the fee rates read from the fees contract are cached for. The fees
+/// contract calculates the fee of every forwarding if it's zero.
+pub fn set_fee_cache_ttl<I: IO + Env + PromiseHandler>(mut io: I) {
+    owner_state(&io);
+
+    let blocks: u64 = io.read_input_borsh().sdk_unwrap();
+    FeeCacheTtl::save(&mut io, blocks);
+}
+
+/// Loads the state of the method which could be called by the owner only.
+fn owner_state<I: IO + Env>(io: &I) -> State {
+    let state = State::load(io).sdk_expect(ContractError::NotInitialized);
+
+    if !state.is_owner(io.predecessor_account_id()) {
+        panic_utf8(ContractError::NotOwner.as_ref());
+    }
+
+    state
+}
+
+fn read_owner_token_input<I: IO + Env>(io: &I) -> AccountId {
+    owner_state(io);
+    io.read_input_borsh().sdk_unwrap()
+}
+
+/// Releases the lock of the token which is left by a forwarding that didn't finish, e.g. because
+/// its callback ran out of gas.
+pub fn unlock<I: IO + Env + PromiseHandler>(mut io: I) {
+    owner_state(&io);
+
+    let token_id: AccountId = io.read_input_borsh().sdk_unwrap();
+    ForwardLock::unlock(&mut io, &token_id);
+}
+
+/// Changes the address the tokens are forwarded to on the current network.
+pub fn set_target<I: IO + Env + PromiseHandler>(mut io: I) {
+    let state = owner_state(&io);
+
+    let Target::Evm { network, .. } = state.target else {
+        panic_utf8(ContractError::WrongTargetKind.as_ref());
+    };
+    let target_address: Address = io.read_input_borsh().sdk_unwrap();
+    let target = Target::Evm {
+        network,
+        targets: Targets::single(target_address),
+    };
+    retarget(&mut io, &state, target);
+}
+
+/// Splits the forwarded tokens between several addresses proportionally to their weights. Every
+/// transfer to an address gets the transfer gas, so the forwarding needs more gas.
+///
+/// Fails with `ERR_NOT_ENOUGH_GAS` if the forwarding to the addresses doesn't fit a single call.
+///
+/// The fee of the split tokens is calculated by `calculate_split_fees`, which the fees contracts
+/// deployed before it don't have. So several addresses are set by `set_targets_callback` only
+/// once the fees contract answers the call of the method.
+pub fn set_targets<I: IO + Env + PromiseHandler>(mut io: I) {
+    let state = owner_state(&io);
+
+    let Target::Evm { network, .. } = state.target else {
+        panic_utf8(ContractError::WrongTargetKind.as_ref());
+    };
+    let targets = Targets::new(io.read_input_borsh().sdk_unwrap()).sdk_unwrap();
+    gas::check_targets(targets.as_slice().len()).sdk_unwrap();
+    let callback_args = types::to_borsh(&targets).sdk_unwrap();
+    let target = Target::Evm { network, targets };
+
+    if !target.is_split() {
+        return retarget(&mut io, &state, target);
+    }
+
+    let promise_id = unsafe {
+        io.promise_create_with_callback(&PromiseWithCallbackArgs {
+            base: PromiseCreateArgs {
+                target_account_id: state.fees_contract_id,
+                method: CALCULATE_SPLIT_FEES_METHOD,
+                args: types::to_borsh(&FeesParams {
+                    amount: 0,
+                    token_id: &state.wnear_contract_id,
+                    target: &target,
+                })
+                .sdk_unwrap(),
+                attached_balance: ZERO_YOCTO,
+                attached_gas: gas::CALCULATE_FEES.min,
+            },
+            callback: PromiseCreateArgs {
+                target_account_id: io.current_account_id(),
+                method: "set_targets_callback",
+                args: callback_args,
+                attached_balance: ZERO_YOCTO,
+                attached_gas: gas::EXECUTION_GAS,
+            },
+        })
+    };
+
+    io.promise_return(promise_id);
+}
+
+/// Sets the addresses passed by `set_targets` if the fees contract has `calculate_split_fees`.
+/// Fails with `ERR_SPLIT_FEES_NOT_SUPPORTED` otherwise.
+pub fn set_targets_callback<I: IO + Env + PromiseHandler>(mut io: I) {
+    io.assert_private_call().sdk_unwrap();
+
+    let targets: Targets = io.read_input_borsh().sdk_unwrap();
+
+    if !matches!(
+        io.promise_result(0)
+            .sdk_expect(ContractError::NoPromiseResult),
+        PromiseResult::Successful(_) | PromiseResult::TooLarge(_)
+    ) {
+        panic_utf8(ContractError::SplitFeesNotSupported.as_ref());
+    }
+
+    let state = State::load(&io).sdk_expect(ContractError::NotInitialized);
+    let Target::Evm { network, .. } = state.target else {
+        panic_utf8(ContractError::WrongTargetKind.as_ref());
+    };
+    retarget(&mut io, &state, Target::Evm { network, targets });
+}
+
+/// Changes the network the tokens are forwarded to.
+pub fn set_target_network<I: IO + Env + PromiseHandler>(mut io: I) {
+    let state = owner_state(&io);
+
+    let Target::Evm { targets, .. } = &state.target else {
+        panic_utf8(ContractError::WrongTargetKind.as_ref());
+    };
+    let target = Target::Evm {
+        network: io.read_input_borsh().sdk_unwrap(),
+        targets: targets.clone(),
+    };
+    retarget(&mut io, &state, target);
+}
+
+/// Changes the NEAR account the tokens are sent to.
+pub fn set_target_account<I: IO + Env + PromiseHandler>(mut io: I) {
+    let state = owner_state(&io);
+
+    if !matches!(state.target, Target::Near { .. }) {
+        panic_utf8(ContractError::WrongTargetKind.as_ref());
+    }
+
+    let target = Target::Near {
+        account_id: io.read_input_borsh().sdk_unwrap(),
+    };
+    retarget(&mut io, &state, target);
+}
+
+fn retarget<I: IO>(io: &mut I, state: &State, target: Target) {
+    let original_target =
+        OriginalTarget::load(io).unwrap_or_else(|| OriginalTarget(state.target.primary()));
+    let event = TargetChanged {
+        old_target: &state.target,
+        target: &target,
+    };
+    event.emit();
+
+    let state = State {
+        target,
+        msg_format: state.msg_format,
+        reserve_margin: state.reserve_margin,
+        max_fee_bps: state.max_fee_bps,
+        caller_policy: state.caller_policy,
+        wnear_contract_id: state.wnear_contract_id,
+        fees_contract_id: state.fees_contract_id,
+        owner_id: state.owner_id,
+    };
+    state.save(io);
+
+    // The original target is kept only while it differs from the current one.
+    if original_target.is_target_of(&state) {
+        OriginalTarget::remove(io);
+    } else {
+        original_target.save(io);
+    }
+}
+
+/// Changes the format of the `msg` passed with the tokens to the target network.
+pub fn set_msg_format<I: IO + Env + PromiseHandler>(mut io: I) {
+    let mut state = owner_state(&io);
+
+    state.msg_format = io.read_input_borsh().sdk_unwrap();
+    state.save(&mut io);
+}
+
+/// Changes the balance kept on top of the storage staking when native NEAR is forwarded.
+pub fn set_reserve_margin<I: IO + Env + PromiseHandler>(mut io: I) {
+    let mut state = owner_state(&io);
+
+    state.reserve_margin = io.read_input_borsh().sdk_unwrap();
+    state.save(&mut io);
+}
+
+pub fn get_state<I: IO + Env + PromiseHandler>(mut io: I) {
+    let state = State::load(&io).sdk_expect(ContractError::NotInitialized);
+    io.return_output(params::state_json(&state).sdk_unwrap().as_bytes());
+}
+
+pub fn get_target_address<I: IO + Env + PromiseHandler>(mut io: I) {
+    let state = State::load(&io).sdk_expect(ContractError::NotInitialized);
+    let Target::Evm { targets, .. } = &state.target else {
+        panic_utf8(ContractError::WrongTargetKind.as_ref());
+    };
+    let address = params::address_to_hex(targets.primary());
+    io.return_output(
+        params::json_string(address.as_str())
+            .sdk_unwrap()
+            .as_bytes(),
+    );
+}
+
+/// Returns the addresses the forwarded tokens are split between with their weights.
+pub fn get_targets<I: IO + Env + PromiseHandler>(mut io: I) {
+    let state = State::load(&io).sdk_expect(ContractError::NotInitialized);
+    let Target::Evm { targets, .. } = &state.target else {
+        panic_utf8(ContractError::WrongTargetKind.as_ref());
+    };
+    io.return_output(params::targets_json(targets).sdk_unwrap().as_bytes());
+}
+
+pub fn get_target_network<I: IO + Env + PromiseHandler>(mut io: I) {
+    let state = State::load(&io).sdk_expect(ContractError::NotInitialized);
+    let Target::Evm { network, .. } = &state.target else {
+        panic_utf8(ContractError::WrongTargetKind.as_ref());
+    };
+    io.return_output(
+        params::json_string(network.as_str())
+            .sdk_unwrap()
+            .as_bytes(),
+    );
+}
+
+/// Returns the NEAR account the tokens are sent to.
+pub fn get_target_account_id<I: IO + Env + PromiseHandler>(mut io: I) {
+    let state = State::load(&io).sdk_expect(ContractError::NotInitialized);
+    let Target::Near { account_id } = &state.target else {
+        panic_utf8(ContractError::WrongTargetKind.as_ref());
+    };
+    io.return_output(
+        params::json_string(account_id.as_str())
+            .sdk_unwrap()
+            .as_bytes(),
+    );
+}
+
+/// Returns the balance which is kept when native NEAR is forwarded.
+pub fn get_native_reserve<I: IO + Env + PromiseHandler>(mut io: I) {
+    let state = State::load(&io).sdk_expect(ContractError::NotInitialized);
+    let reserve = params::amount_to_str(state.native_reserve(io.storage_usage()));
+    io.return_output(
+        params::json_string(reserve.as_str())
+            .sdk_unwrap()
+            .as_bytes(),
+    );
+}
+
+/// Returns the accrued fee of the token passed as the borsh input.
+pub fn get_accrued_fee<I: IO + Env + PromiseHandler>(mut io: I) {
+    let token_id: AccountId = io.read_input_borsh().sdk_unwrap();
+    let fee = params::amount_to_str(AccruedFee::load(&io, &token_id));
+    io.return_output(params::json_string(fee.as_str()).sdk_unwrap().as_bytes());
+}
+
+/// Returns the maximum ratio of the fee to the forwarded amount in basis points.
+pub fn get_max_fee_bps<I: IO + Env + PromiseHandler>(mut io: I) {
+    let state = State::load(&io).sdk_expect(ContractError::NotInitialized);
+    io.return_output(params::amount_to_str(u128::from(state.max_fee_bps)).as_bytes());
+}
+
+pub fn get_msg_format<I: IO + Env + PromiseHandler>(mut io: I) {
+    let state = State::load(&io).sdk_expect(ContractError::NotInitialized);
+    io.return_output(
+        params::msg_format_json(&state.msg_format)
+            .sdk_unwrap()
+            .as_bytes(),
+    );
+}
+
+/// Returns the minimum gas of the transfers of the token passed as the borsh input.
+pub fn get_transfer_gas<I: IO + Env + PromiseHandler>(mut io: I) {
+    let token_id: AccountId = io.read_input_borsh().sdk_unwrap();
+    let gas = TransferGas::load(&io, &token_id);
+    io.return_output(params::amount_to_str(u128::from(gas)).as_bytes());
+}
+
+/// Returns `true` if the token passed as the borsh input could be forwarded.
+pub fn is_token_allowed<I: IO + Env + PromiseHandler>(mut io: I) {
+    let token_id: AccountId = io.read_input_borsh().sdk_unwrap();
+    let result: &[u8] = if TokenList::is_allowed(&io, &token_id) {
+        b"true"
+    } else {
+        b"false"
+    };
+    io.return_output(result);
+}
+
+/// Returns `true` if the forwarding of the token passed as the borsh input is in progress.
+pub fn is_locked<I: IO + Env + PromiseHandler>(mut io: I) {
+    let token_id: AccountId = io.read_input_borsh().sdk_unwrap();
+    let result: &[u8] = if ForwardLock::is_locked(&io, &token_id) {
+        b"true"
+    } else {
+        b"false"
+    };
+    io.return_output(result);
+}
+
+/// Returns `true` if the target differs from the one the forwarder was created with.
+pub fn is_retargeted<I: IO + Env + PromiseHandler>(mut io: I) {
+    let result: &[u8] = if OriginalTarget::load(&io).is_some() {
+        b"true"
+    } else {
+        b"false"
+    };
+    io.return_output(result);
+}
+
+/// Returns the target the forwarder was created with. The account id of the forwarder is
+/// derived from it.
+pub fn get_original_target<I: IO + Env + PromiseHandler>(mut io: I) {
+    let OriginalTarget(target) = OriginalTarget::load(&io).unwrap_or_else(|| {
+        let state = State::load(&io).sdk_expect(ContractError::NotInitialized);
+        OriginalTarget(state.target.primary())
+    });
+    io.return_output(params::target_json(&target).sdk_unwrap().as_bytes());
+}
+
+pub fn get_fees_contract_id<I: IO + Env + PromiseHandler>(mut io: I) {
+    let state = State::load(&io).sdk_expect(ContractError::NotInitialized);
+    io.return_output(
+        params::json_string(state.fees_contract_id.as_str())
+            .sdk_unwrap()
+            .as_bytes(),
+    );
+}
+
+pub fn get_owner_id<I: IO + Env + PromiseHandler>(mut io: I) {
+    let state = State::load(&io).sdk_expect(ContractError::NotInitialized);
+    io.return_output(
+        params::json_string(state.owner_id.as_str())
+            .sdk_unwrap()
+            .as_bytes(),
+    );
+}
+
+pub fn destroy<I: IO + Env + PromiseHandler>(mut io: I) {
+    owner_state(&io);
+
+    let predecessor_id = io.predecessor_account_id();
+    let current_account_id = io.current_account_id();
+    let promise = PromiseBatchAction {
+        target_account_id: current_account_id,
+        actions: [PromiseAction::DeleteAccount {
+            beneficiary_id: predecessor_id,
+        }],
+    };
+
+    let promise_id = unsafe { io.promise_create_batch(&promise) };
+    io.promise_return(promise_id);
+}
+
+#[cfg(test)]
+fn test_runtime() -> MockRuntime {
+    let mut io = MockRuntime::new("forwarder.near");
+    state::test_state(Targets::single(types::Address([1; 20]))).save(&mut io);
+    io
+}
+
+/// Runtime of the forwarder which splits the tokens equally between the given number of targets.
+#[cfg(test)]
+fn split_test_runtime(count: u8) -> MockRuntime {
+    let targets = (1..=count)
+        .map(|i| state::WeightedAddress {
+            address: types::Address([i; 20]),
+            weight: 1,
+        })
+        .collect::<ArrayVec<_, MAX_TARGETS>>();
+    let mut io = MockRuntime::new("forwarder.near");
+    state::test_state(Targets::new(targets.into()).unwrap()).save(&mut io);
+    io
+}
+
+#[cfg(test)]
+fn usdt() -> AccountId {
+    AccountId::new("usdt.near").unwrap()
+}
+
+#[test]
+fn test_upgrade_with_large_code() {
+    // The code which is larger than 128 KiB is deployed as a whole.
+    let code = std::vec![1; 128 * 1024 + 1];
+    let mut io = test_runtime().predecessor("owner.near").input(&code);
+    upgrade(&mut io);
+
+    assert_eq!(
+        io.promises[0],
+        MockPromise::Batch {
+            target_account_id: io.current_account_id,
+            actions: std::vec![
+                MockAction::DeployContract {
+                    code_len: code.len()
+                },
+                MockAction::FunctionCall {
+                    method: "migrate",
+                    gas: gas::MIGRATE_GAS,
+                },
+            ],
+        }
+    );
+}
+
+#[test]
+fn test_forward_nep141_token() {
+    let mut io = test_runtime()
+        .predecessor("owner.near")
+        .deposit(1)
+        .input_borsh(&ForwardParams {
+            token_id: usdt(),
+            amount: None,
+        });
+    forward(&mut io);
+
+    let calls = io.calls();
+    assert_eq!(calls.len(), 2);
+    assert_eq!(calls[0].target_account_id, usdt());
+    assert_eq!(calls[0].method, "ft_balance_of");
+    assert_eq!(calls[0].attached_gas, gas::FT_BALANCE.min);
+    assert_eq!(calls[1].target_account_id, io.current_account_id);
+    assert_eq!(calls[1].method, "calculate_fees_callback");
+    assert!(matches!(
+        io.promises[1],
+        MockPromise::Callback { base, .. } if base == PromiseId::new(0)
+    ));
+    assert_eq!(io.returned_promise, Some(PromiseId::new(1)));
+    assert!(ForwardLock::is_locked(&io, &usdt()));
+    assert!(MockRuntime::logs()[0].contains("forward_started"));
+}
+
+#[test]
+#[should_panic(expected = "ERR_ONE_YOCTO")]
+fn test_forward_without_one_yocto() {
+    let mut io = test_runtime().input_borsh(&ForwardParams {
+        token_id: usdt(),
+        amount: None,
+    });
+    forward(&mut io);
+}
+
+#[test]
+fn test_forward_with_caller_policy() {
+    let forward_params = ForwardParams {
+        token_id: usdt(),
+        amount: None,
+    };
+    let mut io = test_runtime()
+        .predecessor("owner.near")
+        .input_borsh(&CallerPolicy::Relayers);
+    set_caller_policy(&mut io);
+    let mut io = io
+        .next_call()
+        .predecessor("owner.near")
+        .input_borsh(&AccountId::new("relayer.near").unwrap());
+    add_relayer(&mut io);
+
+    assert_eq!(
+        State::load(&io).unwrap().caller_policy,
+        CallerPolicy::Relayers
+    );
+
+    let mut io = io
+        .next_call()
+        .predecessor("relayer.near")
+        .deposit(1)
+        .input_borsh(&forward_params);
+    forward(&mut io);
+
+    assert_eq!(io.calls().len(), 2);
+
+    // The owner could always start the forwarding.
+    let mut io = io
+        .next_call()
+        .predecessor("owner.near")
+        .deposit(1)
+        .input_borsh(&forward_params);
+    ForwardLock::unlock(&mut io, &usdt());
+    forward(&mut io);
+
+    assert_eq!(io.calls().len(), 2);
+}
+
+#[test]
+#[should_panic(expected = "ERR_CALLER_NOT_ALLOWED")]
+fn test_forward_by_removed_relayer() {
+    let mut io = test_runtime()
+        .predecessor("owner.near")
+        .input_borsh(&CallerPolicy::Relayers);
+    set_caller_policy(&mut io);
+    let relayer_id = AccountId::new("relayer.near").unwrap();
+    Relayers::add(&mut io, &relayer_id);
+    let mut io = io
+        .next_call()
+        .predecessor("owner.near")
+        .input_borsh(&relayer_id);
+    remove_relayer(&mut io);
+
+    let mut io = io
+        .next_call()
+        .predecessor("relayer.near")
+        .deposit(1)
+        .input_borsh(&ForwardParams {
+            token_id: usdt(),
+            amount: None,
+        });
+    forward(&mut io);
+}
+
+#[test]
+fn test_forward_many() {
+    let usdc = AccountId::new("usdc.near").unwrap();
+    let mut io = test_runtime()
+        .deposit(1)
+        .gas(300_000_000_000_000)
+        .input_borsh(&std::vec![usdt(), usdc]);
+    forward_many(&mut io);
+
+    let calls = io.calls();
+    assert_eq!(calls.len(), 4);
+    assert_eq!(calls[0].target_account_id, usdt());
+    assert_eq!(calls[2].target_account_id, usdc);
+    assert_eq!(io.returned_promise, None);
+    assert!(ForwardLock::is_locked(&io, &usdt()));
+    assert!(ForwardLock::is_locked(&io, &usdc));
+}
+
+#[test]
+#[should_panic(expected = "ERR_CALLER_NOT_ALLOWED")]
+fn test_forward_many_by_other_account() {
+    let mut io = test_runtime()
+        .predecessor("owner.near")
+        .input_borsh(&CallerPolicy::Owner);
+    set_caller_policy(&mut io);
+
+    let mut io = io
+        .next_call()
+        .predecessor("alice.near")
+        .deposit(1)
+        .input_borsh(&std::vec![usdt()]);
+    forward_many(&mut io);
+}
+
+#[test]
+#[should_panic(expected = "ERR_FORWARD_IN_PROGRESS")]
+fn test_forward_locked_token() {
+    let mut io = test_runtime().deposit(1).input_borsh(&ForwardParams {
+        token_id: usdt(),
+        amount: None,
+    });
+    ForwardLock::lock(&mut io, &usdt());
+    forward(&mut io);
+}
+
+#[test]
+fn test_forward_native_token() {
+    let mut io = test_runtime()
+        .predecessor("owner.near")
+        .deposit(1)
+        .balance(10 * state::DEFAULT_RESERVE_MARGIN)
+        .input_borsh(&ForwardParams {
+            token_id: AccountId::new(NEAR).unwrap(),
+            amount: None,
+        });
+    forward(&mut io);
+
+    let amount = 9 * state::DEFAULT_RESERVE_MARGIN;
+    let calls = io.calls();
+    assert_eq!(calls.len(), 3);
+    assert_eq!(calls[0].target_account_id.as_str(), "wnear.near");
+    assert_eq!(calls[0].method, "near_deposit");
+    assert_eq!(calls[0].attached_balance, amount);
+    assert_eq!(calls[1].method, CALCULATE_FEES_METHOD);
+    assert_eq!(calls[2].method, "finish_forward_callback");
+
+    let params = FinishForwardParams::try_from_slice(&calls[2].args).unwrap();
+    assert_eq!(params.amount, amount);
+    assert_eq!(params.promise_idx, 1);
+    assert!(ForwardLock::is_locked(&io, &params.token_id));
+}
+
+#[test]
+#[should_panic(expected = "ERR_TOKEN_NOT_ALLOWED")]
+fn test_forward_native_token_with_denied_wnear() {
+    // Native NEAR is forwarded as wNEAR, so it's denied together with wNEAR.
+    let mut io = test_runtime()
+        .predecessor("owner.near")
+        .deposit(1)
+        .balance(10 * state::DEFAULT_RESERVE_MARGIN)
+        .input_borsh(&ForwardParams {
+            token_id: AccountId::new(NEAR).unwrap(),
+            amount: None,
+        });
+    TokenList::deny(&mut io, &AccountId::new("wnear.near").unwrap());
+    forward(&mut io);
+}
+
+#[test]
+#[should_panic(expected = "ERR_NOT_ENOUGH_GAS")]
+fn test_forward_with_not_enough_gas() {
+    let mut io = test_runtime()
+        .deposit(1)
+        .gas(50_000_000_000_000)
+        .input_borsh(&ForwardParams {
+            token_id: usdt(),
+            amount: None,
+        });
+    forward(&mut io);
+}
+
+#[test]
+#[should_panic(expected = "ERR_NOT_ENOUGH_GAS")]
+fn test_forward_with_transfer_gas_per_target() {
+    // Four transfers with 80 TGas each don't fit the maximum gas of the call.
+    let mut io = split_test_runtime(4)
+        .predecessor("owner.near")
+        .input_borsh(&TransferGasParams {
+            token_id: usdt(),
+            gas: Some(80_000_000_000_000),
+        });
+    set_transfer_gas(&mut io);
+
+    let mut io = io
+        .next_call()
+        .deposit(1)
+        .gas(300_000_000_000_000)
+        .input_borsh(&ForwardParams {
+            token_id: usdt(),
+            amount: None,
+        });
+    forward(&mut io);
+}
+
+#[test]
+fn test_forward_with_lower_transfer_gas() {
+    let mut io = split_test_runtime(4)
+        .predecessor("owner.near")
+        .input_borsh(&TransferGasParams {
+            token_id: usdt(),
+            gas: Some(20_000_000_000_000),
+        });
+    set_transfer_gas(&mut io);
+
+    let mut io = io
+        .next_call()
+        .deposit(1)
+        .gas(300_000_000_000_000)
+        .input_borsh(&ForwardParams {
+            token_id: usdt(),
+            amount: None,
+        });
+    forward(&mut io);
+
+    let calls = io.calls();
+    assert_eq!(calls[0].method, "ft_balance_of");
+    // The callback gets the gas of the four transfers.
+    assert!(calls[1].attached_gas >= 4 * 20_000_000_000_000);
+}
+
+#[cfg(test)]
+fn weighted_addresses(count: u8) -> std::vec::Vec<state::WeightedAddress> {
+    (1..=count)
+        .map(|i| state::WeightedAddress {
+            address: types::Address([i; 20]),
+            weight: 1,
+        })
+        .collect()
+}
+
+#[test]
+fn test_set_targets() {
+    let mut io = test_runtime()
+        .predecessor("owner.near")
+        .input_borsh(&weighted_addresses(2));
+    set_targets(&mut io);
+
+    // The targets are set once the fees contract answers `calculate_split_fees`.
+    let calls = io.calls();
+    assert_eq!(calls.len(), 2);
+    assert_eq!(calls[0].target_account_id.as_str(), "fees.near");
+    assert_eq!(calls[0].method, CALCULATE_SPLIT_FEES_METHOD);
+    assert_eq!(calls[1].method, "set_targets_callback");
+    assert_eq!(io.returned_promise, Some(PromiseId::new(1)));
+    assert!(!State::load(&io).unwrap().target.is_split());
+
+    let args = calls[1].args.clone();
+    let mut io = io
+        .next_call()
+        .input(&args)
+        .promise_result(PromiseResult::Successful(Vec::new()));
+    set_targets_callback(&mut io);
+
+    let Target::Evm { targets, .. } = State::load(&io).unwrap().target else {
+        panic!("EVM target expected");
+    };
+    assert_eq!(targets.as_slice(), weighted_addresses(2).as_slice());
+    assert!(MockRuntime::logs()
+        .iter()
+        .any(|log| log.contains("target_changed")));
+}
+
+#[test]
+fn test_set_single_target() {
+    // A single address doesn't need `calculate_split_fees`.
+    let mut io = split_test_runtime(2)
+        .predecessor("owner.near")
+        .input_borsh(&weighted_addresses(1));
+    set_targets(&mut io);
+
+    assert!(io.calls().is_empty());
+    assert!(!State::load(&io).unwrap().target.is_split());
+}
+
+#[test]
+#[should_panic(expected = "ERR_SPLIT_FEES_NOT_SUPPORTED")]
+fn test_set_targets_without_split_fees() {
+    let mut io = test_runtime()
+        .predecessor("owner.near")
+        .input_borsh(&weighted_addresses(2));
+    set_targets(&mut io);
+
+    let args = io.calls()[1].args.clone();
+    let mut io = io
+        .next_call()
+        .input(&args)
+        .promise_result(PromiseResult::Failed);
+    set_targets_callback(&mut io);
+}
+
+#[test]
+fn test_calculate_fees_callback() {
+    let mut io = test_runtime()
+        .input_borsh(&ForwardParams {
+            token_id: usdt(),
+            amount: Some(600),
+        })
+        .promise_result(PromiseResult::Successful(
+            br#""1000""#.as_slice().try_into().unwrap(),
+        ));
+    calculate_fees_callback(&mut io);
+
+    let calls = io.calls();
+    assert_eq!(calls.len(), 2);
+    assert_eq!(calls[0].target_account_id.as_str(), "fees.near");
+    assert_eq!(calls[0].method, CALCULATE_FEES_METHOD);
+    assert_eq!(calls[1].method, "finish_forward_callback");
+
+    let params = FinishForwardParams::try_from_slice(&calls[1].args).unwrap();
+    assert_eq!(params.amount, 600);
+    assert_eq!(params.token_id, usdt());
+    assert_eq!(io.returned_promise, Some(PromiseId::new(1)));
+}
+
+#[test]
+fn test_calculate_fees_callback_with_split() {
+    // Only the fees contract which supports the split is asked for the fee of the split tokens.
+    let mut io = split_test_runtime(2)
+        .input_borsh(&ForwardParams {
+            token_id: usdt(),
+            amount: None,
+        })
+        .promise_result(PromiseResult::Successful(
+            br#""1000""#.as_slice().try_into().unwrap(),
+        ));
+    calculate_fees_callback(&mut io);
+
+    let calls = io.calls();
+    assert_eq!(calls[0].target_account_id.as_str(), "fees.near");
+    assert_eq!(calls[0].method, CALCULATE_SPLIT_FEES_METHOD);
+}
+
+#[test]
+fn test_forward_with_fee_rate_cache() {
+    let forward_params = ForwardParams {
+        token_id: usdt(),
+        amount: None,
+    };
+    let mut io = test_runtime()
+        .predecessor("owner.near")
+        .input_borsh(&100u64);
+    set_fee_cache_ttl(&mut io);
+
+    // The fee rate isn't cached yet, so it's read along with the balance.
+    let mut io = io
+        .next_call()
+        .predecessor("owner.near")
+        .deposit(1)
+        .block_height(10)
+        .input_borsh(&forward_params);
+    forward(&mut io);
+
+    let calls = io.calls();
+    assert_eq!(calls.len(), 3);
+    assert_eq!(calls[0].method, "ft_balance_of");
+    assert_eq!(calls[1].target_account_id.as_str(), "fees.near");
+    assert_eq!(calls[1].method, FEE_RATE_METHOD);
+    assert_eq!(calls[1].attached_gas, gas::FEE_RATE.min);
+    assert_eq!(calls[2].method, "calculate_fees_callback");
+    assert!(matches!(io.promises[2], MockPromise::And(_)));
+
+    // The fee is calculated with the rate, so the tokens are transferred right away.
+    let mut io = io
+        .next_call()
+        .block_height(10)
+        .input_borsh(&forward_params)
+        .promise_result(PromiseResult::Successful(
+            br#""1000""#.as_slice().try_into().unwrap(),
+        ))
+        .promise_result(PromiseResult::Successful(types::to_borsh(&50u64).unwrap()));
+    calculate_fees_callback(&mut io);
+
+    let calls = io.calls();
+    assert_eq!(calls.len(), 2);
+    assert_eq!(calls[0].method, "ft_transfer_call");
+    assert_eq!(calls[1].method, "resolve_forward_callback");
+    let params = ResolveForwardParams::try_from_slice(&calls[1].args).unwrap();
+    assert_eq!(params.fee, 5);
+    assert_eq!(
+        FeeRate::load(&io, &usdt()),
+        Some(FeeRate {
+            rate_bps: 50,
+            expires_at: 110,
+        })
+    );
+
+    // The cached rate is used until it expires.
+    ForwardLock::unlock(&mut io, &usdt());
+    let mut io = io
+        .next_call()
+        .predecessor("owner.near")
+        .deposit(1)
+        .block_height(109)
+        .input_borsh(&forward_params);
+    forward(&mut io);
+
+    assert_eq!(io.calls().len(), 2);
+
+    let mut io = io
+        .next_call()
+        .block_height(109)
+        .input_borsh(&forward_params)
+        .promise_result(PromiseResult::Successful(
+            br#""1000""#.as_slice().try_into().unwrap(),
+        ));
+    calculate_fees_callback(&mut io);
+
+    assert_eq!(io.calls()[0].method, "ft_transfer_call");
+    // The expired rate is read again by the next forwarding.
+    assert!(FeeRate::needs_refresh(
+        &io.next_call().block_height(110),
+        &usdt()
+    ));
+}
+
+#[test]
+fn test_calculate_fees_callback_without_fee_rate() {
+    let mut io = test_runtime()
+        .input_borsh(&100u64)
+        .predecessor("owner.near");
+    set_fee_cache_ttl(&mut io);
+
+    // The fees contract which doesn't provide the rate calculates the fee itself.
+    let mut io = io
+        .next_call()
+        .input_borsh(&ForwardParams {
+            token_id: usdt(),
+            amount: None,
+        })
+        .promise_result(PromiseResult::Successful(
+            br#""1000""#.as_slice().try_into().unwrap(),
+        ))
+        .promise_result(PromiseResult::Failed);
+    calculate_fees_callback(&mut io);
+
+    let calls = io.calls();
+    assert_eq!(calls[0].method, CALCULATE_FEES_METHOD);
+    assert_eq!(calls[1].method, "finish_forward_callback");
+    assert_eq!(FeeRate::load(&io, &usdt()), None);
+}
+
+#[test]
+fn test_calculate_fees_callback_with_amount_above_balance() {
+    let mut io = test_runtime()
+        .input_borsh(&ForwardParams {
+            token_id: usdt(),
+            amount: Some(1001),
+        })
+        .promise_result(PromiseResult::Successful(
+            br#""1000""#.as_slice().try_into().unwrap(),
+        ));
+    ForwardLock::lock(&mut io, &usdt());
+    calculate_fees_callback(&mut io);
+
+    assert!(io.promises.is_empty());
+    assert!(!ForwardLock::is_locked(&io, &usdt()));
+    assert!(MockRuntime::logs()[0].contains("forward_failed"));
+}
+
+#[test]
+fn test_calculate_fees_callback_with_bad_result() {
+    // The whole result is parsed unless it's too large.
+    let mut io = test_runtime()
+        .input_borsh(&ForwardParams {
+            token_id: usdt(),
+            amount: Some(600),
+        })
+        .promise_result(PromiseResult::Successful(
+            br#""1000", "extra""#.as_slice().try_into().unwrap(),
+        ));
+    ForwardLock::lock(&mut io, &usdt());
+    calculate_fees_callback(&mut io);
+
+    assert!(io.promises.is_empty());
+    assert!(!ForwardLock::is_locked(&io, &usdt()));
+    assert!(MockRuntime::logs()[0].contains("ERR_BAD_JSON"));
+}
+
+#[test]
+fn test_calculate_fees_callback_with_large_result() {
+    // Only the beginning of the result which doesn't fit the buffer is read.
+    let mut io = test_runtime()
+        .input_borsh(&ForwardParams {
+            token_id: usdt(),
+            amount: Some(600),
+        })
+        .promise_result(PromiseResult::TooLarge(
+            br#""1000", "extra""#.as_slice().try_into().unwrap(),
+        ));
+    calculate_fees_callback(&mut io);
+
+    assert_eq!(io.calls().len(), 2);
+
+    let mut io = io
+        .next_call()
+        .input_borsh(&ForwardParams {
+            token_id: usdt(),
+            amount: Some(600),
+        })
+        .promise_result(PromiseResult::TooLarge(
+            br#"{"balance":"1000""#.as_slice().try_into().unwrap(),
+        ));
+    ForwardLock::lock(&mut io, &usdt());
+    calculate_fees_callback(&mut io);
+
+    assert!(io.promises.is_empty());
+    assert!(MockRuntime::logs()[0].contains("ERR_RESULT_TOO_LARGE"));
+}
+
+#[test]
+fn test_finish_forward_callback() {
+    let mut io = test_runtime()
+        .input_borsh(&FinishForwardParams {
+            amount: 1000,
+            token_id: usdt(),
+            promise_idx: 0,
+        })
+        .promise_result(PromiseResult::Successful(types::to_borsh(&10u128).unwrap()));
+    finish_forward_callback(&mut io);
+
+    let calls = io.calls();
+    assert_eq!(calls.len(), 2);
+    assert_eq!(calls[0].target_account_id, usdt());
+    assert_eq!(calls[0].method, "ft_transfer_call");
+    assert_eq!(calls[0].attached_balance, 1);
+    assert_eq!(calls[1].method, "resolve_forward_callback");
+
+    let params = ResolveForwardParams::try_from_slice(&calls[1].args).unwrap();
+    assert_eq!(params.amount, 1000);
+    assert_eq!(params.fee, 10);
+    assert_eq!(params.sent.as_slice(), &[(0, 990)]);
+
+    let logs = MockRuntime::logs();
+    assert!(logs[0].contains("fee_quoted"));
+    assert!(logs[1].contains("transfer_sent"));
+}
+
+#[test]
+fn test_finish_forward_callback_with_used_gas() {
+    // The gas already used by the callback isn't attached to the transfers.
+    let mut io = test_runtime()
+        .input_borsh(&FinishForwardParams {
+            amount: 1000,
+            token_id: usdt(),
+            promise_idx: 0,
+        })
+        .promise_result(PromiseResult::Successful(types::to_borsh(&10u128).unwrap()));
+    io.used_gas = 20_000_000_000_000;
+    finish_forward_callback(&mut io);
+
+    let attached = io.calls().iter().map(|call| call.attached_gas).sum::<u64>();
+    assert!(attached <= io.prepaid_gas - io.used_gas);
+}
+
+#[test]
+fn test_finish_forward_callback_with_split() {
+    let mut io = split_test_runtime(2)
+        .input_borsh(&FinishForwardParams {
+            amount: 1000,
+            token_id: usdt(),
+            promise_idx: 0,
+        })
+        .promise_result(PromiseResult::Successful(types::to_borsh(&10u128).unwrap()));
+    finish_forward_callback(&mut io);
+
+    // Every transfer gets the whole transfer gas rather than a share of it.
+    let calls = io.calls();
+    assert_eq!(calls.len(), 3);
+    assert!(calls[..2]
+        .iter()
+        .all(|call| call.method == "ft_transfer_call"
+            && call.attached_gas >= gas::DEFAULT_TRANSFER_GAS));
+    assert_eq!(calls[2].method, "resolve_forward_callback");
+
+    let params = ResolveForwardParams::try_from_slice(&calls[2].args).unwrap();
+    assert_eq!(params.sent.as_slice(), &[(0, 495), (1, 495)]);
+
+    // Every transfer is reported with its address and amount.
+    let logs = MockRuntime::logs();
+    assert!(logs[0].contains(r#""target_addresses":["#));
+    assert!(logs[1].contains(r#""amount":"495","fee":"10","target_network":"target.near","target_address":"0x0101010101010101010101010101010101010101""#));
+    assert!(logs[2].contains(r#""amount":"495","fee":"10","target_network":"target.near","target_address":"0x0202020202020202020202020202020202020202""#));
+}
+
+#[test]
+fn test_finish_forward_callback_with_fee_above_max_ratio() {
+    let mut io = test_runtime()
+        .input_borsh(&FinishForwardParams {
+            amount: 1000,
+            token_id: usdt(),
+            promise_idx: 0,
+        })
+        .promise_result(PromiseResult::Successful(
+            types::to_borsh(&101u128).unwrap(),
+        ));
+    ForwardLock::lock(&mut io, &usdt());
+    finish_forward_callback(&mut io);
+
+    assert!(io.promises.is_empty());
+    assert!(!ForwardLock::is_locked(&io, &usdt()));
+    assert!(MockRuntime::logs()[0].contains("ERR_FEE_TOO_HIGH"));
+}
+
+#[test]
+fn test_finish_forward_callback_with_large_result() {
+    let mut fee = types::to_borsh(&10u128).unwrap();
+    fee.extend([0xff; 16]);
+    let mut io = test_runtime()
+        .input_borsh(&FinishForwardParams {
+            amount: 1000,
+            token_id: usdt(),
+            promise_idx: 0,
+        })
+        .promise_result(PromiseResult::TooLarge(fee));
+    ForwardLock::lock(&mut io, &usdt());
+    finish_forward_callback(&mut io);
+
+    assert!(!io.calls().is_empty());
+}
+
+#[test]
+fn test_ft_on_transfer_with_long_msg() {
+    // The amount is parsed from the beginning of the input which doesn't fit the buffer.
+    let mut input =
+        std::string::String::from(r#"{"sender_id":"alice.near","amount":"1000","msg":""#);
+    input.extend(core::iter::repeat_n('a', 4096));
+    input.push_str(r#""}"#);
+    let mut io = test_runtime()
+        .predecessor("usdt.near")
+        .input(input.as_bytes());
+    ft_on_transfer(&mut io);
+
+    assert_eq!(io.calls().len(), 2);
+    assert_eq!(io.output.as_deref(), Some(br#""0""#.as_slice()));
+}
+
+#[test]
+fn test_resolve_forward_callback() {
+    let params = ResolveForwardParams {
+        amount: 1000,
+        fee: 10,
+        token_id: usdt(),
+        sent: ArrayVec::<_, MAX_TARGETS>::try_from([(0, 990)].as_slice())
+            .unwrap()
+            .into(),
+    };
+    let mut io = test_runtime()
+        .input_borsh(&params)
+        .promise_result(PromiseResult::Successful(
+            br#""990""#.as_slice().try_into().unwrap(),
+        ));
+    ForwardLock::lock(&mut io, &usdt());
+    resolve_forward_callback(&mut io);
+
+    let calls = io.calls();
+    assert_eq!(calls.len(), 2);
+    assert_eq!(calls[0].method, "ft_transfer");
+    assert_eq!(calls[1].method, "resolve_fee_callback");
+    assert_eq!(Refund::load(&io, &usdt()), 0);
+    // The token stays locked until the fee is transferred.
+    assert!(ForwardLock::is_locked(&io, &usdt()));
+
+    // The receipt is passed to the fee callback.
+    let fee_params = ResolveFeeParams::try_from_slice(&calls[1].args).unwrap();
+    assert_eq!(
+        fee_params.receipt,
+        Some(ForwardReceipt {
+            token_id: usdt(),
+            amount: 1000,
+            fee: 10,
+            delivered: 990,
+            transfers: ArrayVec::<_, MAX_TARGETS>::try_from([(0, 990)].as_slice())
+                .unwrap()
+                .into(),
+            refunded: 0,
+            transfer_succeeded: true,
+            fee_succeeded: true,
+        })
+    );
+
+    // The failed transfer is refunded to the forwarder without the fee.
+    let mut io = io
+        .next_call()
+        .input_borsh(&params)
+        .promise_result(PromiseResult::Failed);
+    resolve_forward_callback(&mut io);
+
+    assert!(io.promises.is_empty());
+    assert!(!ForwardLock::is_locked(&io, &usdt()));
+    assert_eq!(Refund::load(&io, &usdt()), 1000);
+    assert!(MockRuntime::logs()[0].contains("ERR_TRANSFER_REFUNDED"));
+
+    // The receipt is returned at once if there is no fee to transfer.
+    let output = std::string::String::from_utf8(io.output.clone().unwrap()).unwrap();
+    assert!(output.starts_with(
+        r#"{"token_id":"usdt.near","amount":"1000","fee":"0","delivered":"0","refunded":"1000","#
+    ));
+    assert!(output.ends_with(r#""transfer_succeeded":false,"fee_succeeded":true}"#));
+}
+
+#[test]
+fn test_forward_lock_until_fee_transfer() {
+    let mut io = test_runtime()
+        .input_borsh(&ResolveForwardParams {
+            amount: 1000,
+            fee: 10,
+            token_id: usdt(),
+            sent: ArrayVec::<_, MAX_TARGETS>::try_from([(0, 990)].as_slice())
+                .unwrap()
+                .into(),
+        })
+        .promise_result(PromiseResult::Successful(
+            br#""990""#.as_slice().try_into().unwrap(),
+        ));
+    ForwardLock::lock(&mut io, &usdt());
+    resolve_forward_callback(&mut io);
+
+    let calls = io.calls();
+    assert_eq!(calls[1].method, "resolve_fee_callback");
+    let fee_args = calls[1].args.clone();
+    assert!(ForwardLock::is_locked(&io, &usdt()));
+
+    // Another forwarding of the token can't start before the fee is transferred.
+    let mut io = io
+        .next_call()
+        .predecessor("usdt.near")
+        .input(br#"{"sender_id":"alice.near","amount":"1000","msg":""}"#);
+    ft_on_transfer(&mut io);
+
+    assert!(io.promises.is_empty());
+    assert_eq!(io.output.as_deref(), Some(br#""1000""#.as_slice()));
+
+    let mut io = io
+        .next_call()
+        .input(&fee_args)
+        .promise_result(PromiseResult::Successful(Vec::new()));
+    resolve_fee_callback(&mut io);
+
+    assert!(!ForwardLock::is_locked(&io, &usdt()));
+}
+
+#[test]
+fn test_resolve_fee_callback() {
+    let params = ResolveFeeParams {
+        token_id: usdt(),
+        fee: 10,
+        receipt: None,
+    };
+    let mut io = test_runtime()
+        .input_borsh(&params)
+        .promise_result(PromiseResult::Successful(Vec::new()));
+    resolve_fee_callback(&mut io);
+
+    assert_eq!(AccruedFee::load(&io, &usdt()), 0);
+
+    let mut io = io
+        .next_call()
+        .input_borsh(&params)
+        .promise_result(PromiseResult::Failed);
+    resolve_fee_callback(&mut io);
+
+    assert_eq!(AccruedFee::load(&io, &usdt()), 10);
+    assert_eq!(io.output, None);
+
+    // The receipt is completed with the result of the fee transfer.
+    let receipt = ForwardReceipt {
+        token_id: usdt(),
+        amount: 1000,
+        fee: 10,
+        delivered: 990,
+        transfers: ArrayVec::<_, MAX_TARGETS>::try_from([(0, 990)].as_slice())
+            .unwrap()
+            .into(),
+        refunded: 0,
+        transfer_succeeded: true,
+        fee_succeeded: true,
+    };
+    let mut io = io
+        .next_call()
+        .input_borsh(&ResolveFeeParams {
+            receipt: Some(receipt),
+            ..params
+        })
+        .promise_result(PromiseResult::Failed);
+    resolve_fee_callback(&mut io);
+
+    let output = std::string::String::from_utf8(io.output.clone().unwrap()).unwrap();
+    assert!(output.ends_with(r#""transfer_succeeded":true,"fee_succeeded":false}"#));
+    assert_eq!(AccruedFee::load(&io, &usdt()), 20);
+}
+
+#[test]
+fn test_reclaim() {
+    let mut io = test_runtime()
+        .predecessor("owner.near")
+        .input_borsh(&ReclaimParams {
+            token_id: usdt(),
+            receiver_id: AccountId::new("alice.near").unwrap(),
+        });
+    Refund::save(&mut io, &usdt(), 1000);
+    reclaim(&mut io);
+
+    let calls = io.calls();
+    assert_eq!(calls.len(), 2);
+    assert_eq!(calls[0].target_account_id, usdt());
+    assert_eq!(calls[0].method, "ft_transfer");
+    assert_eq!(
+        calls[0].args.as_slice(),
+        br#"{"receiver_id":"alice.near","amount":"1000"}"#
+    );
+    assert_eq!(calls[1].method, "resolve_reclaim_callback");
+    assert_eq!(Refund::load(&io, &usdt()), 0);
+
+    // The record is kept once the transfer succeeds.
+    let args = calls[1].args.clone();
+    let mut io = io
+        .next_call()
+        .input(&args)
+        .promise_result(PromiseResult::Successful(Vec::new()));
+    resolve_reclaim_callback(&mut io);
+
+    assert_eq!(Refund::load(&io, &usdt()), 0);
+
+    // The record is restored if the transfer fails.
+    let mut io = io
+        .next_call()
+        .input(&args)
+        .promise_result(PromiseResult::Failed);
+    resolve_reclaim_callback(&mut io);
+
+    assert_eq!(Refund::load(&io, &usdt()), 1000);
+}
+
+#[test]
+fn test_finish_forward_callback_with_failed_deposit() {
+    // The native NEAR which failed to be deposited isn't forwarded as wNEAR.
+    let mut io = test_runtime()
+        .input_borsh(&FinishForwardParams {
+            amount: 1000,
+            token_id: AccountId::new("wnear.near").unwrap(),
+            promise_idx: 1,
+        })
+        .promise_result(PromiseResult::Failed)
+        .promise_result(PromiseResult::Successful(types::to_borsh(&10u128).unwrap()));
+    ForwardLock::lock(&mut io, &AccountId::new("wnear.near").unwrap());
+    finish_forward_callback(&mut io);
+
+    assert!(io.promises.is_empty());
+    assert!(!ForwardLock::is_locked(
+        &io,
+        &AccountId::new("wnear.near").unwrap()
+    ));
+    assert!(MockRuntime::logs()[0].contains("ERR_PROMISE_FAILED"));
+}
+
+#[test]
+fn test_ft_on_transfer_with_cached_fee_rate() {
+    let mut io = test_runtime()
+        .input_borsh(&100u64)
+        .predecessor("owner.near");
+    set_fee_cache_ttl(&mut io);
+    let mut io = io.next_call();
+    FeeRate::save(&mut io, &usdt(), 50);
+
+    let mut io = io
+        .next_call()
+        .predecessor("usdt.near")
+        .input(br#"{"sender_id":"alice.near","amount":"1000","msg":""}"#);
+    ft_on_transfer(&mut io);
+
+    let calls = io.calls();
+    assert_eq!(calls.len(), 2);
+    assert_eq!(calls[0].method, "ft_transfer_call");
+    assert_eq!(calls[1].method, "resolve_forward_callback");
+    assert_eq!(io.output.as_deref(), Some(br#""0""#.as_slice()));
+}
+
+#[test]
+fn test_ft_on_transfer() {
+    let input = br#"{"sender_id":"alice.near","amount":"1000","msg":""}"#;
+    let mut io = test_runtime().predecessor("usdt.near").input(input);
+    ft_on_transfer(&mut io);
+
+    let calls = io.calls();
+    assert_eq!(calls.len(), 2);
+    assert_eq!(calls[0].method, CALCULATE_FEES_METHOD);
+    assert_eq!(calls[1].method, "finish_forward_callback");
+    assert_eq!(io.returned_promise, None);
+    assert_eq!(io.output.as_deref(), Some(br#""0""#.as_slice()));
+    assert!(ForwardLock::is_locked(&io, &usdt()));
+
+    // The denied token is refunded to the sender.
+    let mut io = io.next_call().predecessor("usdt.near").input(input);
+    ForwardLock::unlock(&mut io, &usdt());
+    TokenList::deny(&mut io, &usdt());
+    ft_on_transfer(&mut io);
+
+    assert!(io.promises.is_empty());
+    assert_eq!(io.output.as_deref(), Some(br#""1000""#.as_slice()));
+}
+
+#[test]
+fn test_ft_on_transfer_with_caller_policy() {
+    // The deposit is kept on the forwarder until the owner or a relayer forwards it.
+    for policy in [CallerPolicy::Owner, CallerPolicy::Relayers] {
+        let mut io = test_runtime()
+            .predecessor("owner.near")
+            .input_borsh(&policy);
+        set_caller_policy(&mut io);
+
+        let mut io = io
+            .next_call()
+            .predecessor("usdt.near")
+            .input(br#"{"sender_id":"alice.near","amount":"1000","msg":""}"#);
+        ft_on_transfer(&mut io);
+
+        assert!(io.promises.is_empty());
+        assert_eq!(io.output.as_deref(), Some(br#""0""#.as_slice()));
+        assert!(!ForwardLock::is_locked(&io, &usdt()));
+    }
 }
 
 #[cfg(target_arch = "wasm32")]
@@ -0,0 +1,74 @@
+const LOW_MASK: u128 = u64::MAX as u128;
+
+/// Calculates `a * b / c` rounding down, without overflowing on the intermediate product.
+/// Returns `None` if `c` is zero or the result doesn't fit into `u128`.
+pub const fn mul_div(a: u128, b: u128, c: u128) -> Option<u128> {
+    if c == 0 {
+        return None;
+    }
+
+    if let Some(product) = a.checked_mul(b) {
+        return Some(product / c);
+    }
+
+    let (hi, lo) = widening_mul(a, b);
+
+    if hi >= c {
+        return None;
+    }
+
+    // Long division of the 256-bit product by `c`. Since `hi < c` the quotient fits into `u128`.
+    let mut remainder = hi;
+    let mut quotient = 0;
+    let mut i = 128;
+
+    while i > 0 {
+        i -= 1;
+        let carry = remainder >> 127;
+        remainder = (remainder << 1) | ((lo >> i) & 1);
+
+        if carry == 1 || remainder >= c {
+            remainder = remainder.wrapping_sub(c);
+            quotient |= 1 << i;
+        }
+    }
+
+    Some(quotient)
+}
+
+/// Returns the high and low halves of the 256-bit product `a * b`.
+const fn widening_mul(a: u128, b: u128) -> (u128, u128) {
+    let (a_hi, a_lo) = (a >> 64, a & LOW_MASK);
+    let (b_hi, b_lo) = (b >> 64, b & LOW_MASK);
+
+    let lo_lo = a_lo * b_lo;
+    let lo_hi = a_lo * b_hi;
+    let hi_lo = a_hi * b_lo;
+    let hi_hi = a_hi * b_hi;
+
+    let mid = (lo_lo >> 64) + (lo_hi & LOW_MASK) + (hi_lo & LOW_MASK);
+    let lo = (lo_lo & LOW_MASK) | (mid << 64);
+    let hi = hi_hi + (lo_hi >> 64) + (hi_lo >> 64) + (mid >> 64);
+
+    (hi, lo)
+}
+
+#[test]
+fn test_mul_div() {
+    assert_eq!(mul_div(50, 900, 1000), Some(45));
+    assert_eq!(mul_div(7, 3, 2), Some(10));
+    assert_eq!(mul_div(1, 1, 0), None);
+    assert_eq!(mul_div(0, u128::MAX, 1), Some(0));
+}
+
+#[test]
+fn test_mul_div_with_overflowing_product() {
+    let near = 10u128.pow(24);
+    let fee = 5 * 10u128.pow(22);
+
+    assert_eq!(mul_div(fee, near, near), Some(fee));
+    assert_eq!(mul_div(fee, near / 2, near), Some(fee / 2));
+    assert_eq!(mul_div(u128::MAX, u128::MAX, u128::MAX), Some(u128::MAX));
+    assert_eq!(mul_div(u128::MAX, 3, 4), Some(u128::MAX / 4 * 3 + 2));
+    assert_eq!(mul_div(u128::MAX, 2, 1), None);
+}
@@ -1,49 +1,473 @@
-use arrayvec::ArrayString;
-use borsh::{BorshDeserialize, BorshSerialize};
-use core::str::FromStr;
+use arrayvec::{ArrayString, ArrayVec};
+use borsh::{io, BorshDeserialize, BorshSerialize};
 
 use crate::error::ContractError;
-use crate::runtime::{StorageIntermediate, IO};
-use crate::types::{AccountId, Address, Vec};
+use crate::gas;
+use crate::json::{JsonReader, JsonWriter};
+use crate::math;
+use crate::runtime::{Env, StorageIntermediate, IO, MAX_COMBINED_PROMISES};
+use crate::state::{
+    LegacyState, MsgFormat, State, Target, Targets, BPS_DENOMINATOR, MAX_FEE_BPS, MAX_TARGETS,
+};
+use crate::types::{AccountId, Address, BoundedVec, Vec};
+#[cfg(test)]
+use crate::{state::CallerPolicy, types::ChainId};
 
-const STATE_STORAGE_KEY: &[u8] = b"FWD_STATE";
+const REFUND_STORAGE_PREFIX: &[u8] = b"FWD_REFUND";
+const ACCRUED_FEE_STORAGE_PREFIX: &[u8] = b"FWD_FEE";
+const LOCK_STORAGE_PREFIX: &[u8] = b"FWD_LOCK";
+const TOKEN_LIST_STORAGE_PREFIX: &[u8] = b"FWD_TOKEN";
+const ALLOWED_COUNT_STORAGE_KEY: &[u8] = b"FWD_ALLOWED_COUNT";
+const TRANSFER_GAS_STORAGE_PREFIX: &[u8] = b"FWD_GAS";
+const ORIGINAL_TARGET_STORAGE_KEY: &[u8] = b"FWD_ORIGINAL_TARGET";
+const FEE_RATE_STORAGE_PREFIX: &[u8] = b"FWD_FEE_RATE";
+const FEE_CACHE_TTL_STORAGE_KEY: &[u8] = b"FWD_FEE_CACHE_TTL";
+const RELAYER_STORAGE_PREFIX: &[u8] = b"FWD_RELAYER";
 
-#[derive(BorshSerialize, BorshDeserialize)]
+/// Parameters of the forwarder initialization.
 #[cfg_attr(not(target_arch = "wasm32"), derive(PartialEq, Debug))]
-pub struct State {
-    pub target_address: Address,
-    pub target_network: AccountId,
-    pub wnear_contract_id: AccountId,
-    pub fees_contract_id: AccountId,
-    pub owner_id: AccountId,
+pub struct InitParams {
+    pub state: State,
+    /// Whether the full-access key of the updater should be added to the forwarder.
+    pub add_full_access_key: bool,
+}
+
+impl BorshDeserialize for InitParams {
+    fn deserialize_reader<R: io::Read>(reader: &mut R) -> io::Result<Self> {
+        // The forwarder is initialized with a single target, so the state is passed in the
+        // legacy layout.
+        let mut state: State = LegacyState::deserialize_reader(reader)?.into();
+        // The flag could be missing in the input for compatibility with the factories which
+        // pass the state only. The key is added in this case.
+        let mut flag = [0u8; 1];
+        let add_full_access_key = match (reader.read(&mut flag)?, flag[0]) {
+            (0, _) | (1, 1) => true,
+            (1, 0) => false,
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "Invalid bool representation",
+                ))
+            }
+        };
+        // The optional NEAR account follows the flag. If it's present, the tokens are sent to
+        // the account, and the target address and network of the state are ignored.
+        let mut tag = [0u8; 1];
+        match (reader.read(&mut tag)?, tag[0]) {
+            (0, _) | (1, 0) => {}
+            (1, 1) => {
+                state.target = Target::Near {
+                    account_id: AccountId::deserialize_reader(reader)?,
+                };
+            }
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "Invalid Option representation",
+                ))
+            }
+        }
+        // The optional maximum fee ratio follows the NEAR account. The default ratio is used if
+        // it's missing.
+        let mut bps = [0u8; 2];
+        match reader.read(&mut bps)? {
+            0 => {}
+            2 if u16::from_le_bytes(bps) <= MAX_FEE_BPS => {
+                state.max_fee_bps = u16::from_le_bytes(bps);
+            }
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "Invalid max fee ratio",
+                ))
+            }
+        }
+
+        Ok(Self {
+            state,
+            add_full_access_key,
+        })
+    }
 }
 
-impl State {
+/// Target the forwarder was created with. It's recorded only while the forwarder is retargeted,
+/// because the account id of the forwarder is derived from it.
+#[derive(BorshSerialize, BorshDeserialize)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(PartialEq, Debug))]
+pub struct OriginalTarget(pub Target);
+
+impl OriginalTarget {
     pub fn save<I: IO>(&self, io: &mut I) {
-        io.write_borsh(STATE_STORAGE_KEY, self);
+        io.write_borsh(ORIGINAL_TARGET_STORAGE_KEY, self);
     }
 
     pub fn load<I: IO>(io: &I) -> Option<Self> {
-        let data = io.read_storage(STATE_STORAGE_KEY)?.to_vec();
+        let data = io
+            .read_storage(ORIGINAL_TARGET_STORAGE_KEY)?
+            .to_vec()
+            .ok()?;
         Self::try_from_slice(data.as_slice()).ok()
     }
 
-    pub fn is_owner(&self, account_id: AccountId) -> bool {
-        self.owner_id == account_id
+    pub fn remove<I: IO>(io: &mut I) {
+        io.remove_storage(ORIGINAL_TARGET_STORAGE_KEY);
+    }
+
+    pub fn is_target_of(&self, state: &State) -> bool {
+        !state.target.is_split() && state.target.primary() == self.0
     }
 }
 
-#[derive(BorshSerialize)]
+/// Tokens which were sent to the target network but returned back to the forwarder.
+/// The recorded amount is still held by the forwarder and could be forwarded again or reclaimed.
+pub struct Refund;
+
+impl Refund {
+    pub fn load<I: IO>(io: &I, token_id: &AccountId) -> u128 {
+        load_amount(io, REFUND_STORAGE_PREFIX, token_id)
+    }
+
+    pub fn save<I: IO>(io: &mut I, token_id: &AccountId, amount: u128) {
+        save_amount(io, REFUND_STORAGE_PREFIX, token_id, amount);
+    }
+}
+
+/// Fee of the token which couldn't be transferred to the fees contract, e.g. because the fees
+/// contract isn't registered on the token. The accrued fee is held by the forwarder and isn't
+/// forwarded until it's claimed by the fees contract.
+pub struct AccruedFee;
+
+impl AccruedFee {
+    pub fn load<I: IO>(io: &I, token_id: &AccountId) -> u128 {
+        load_amount(io, ACCRUED_FEE_STORAGE_PREFIX, token_id)
+    }
+
+    pub fn save<I: IO>(io: &mut I, token_id: &AccountId, amount: u128) {
+        save_amount(io, ACCRUED_FEE_STORAGE_PREFIX, token_id, amount);
+    }
+}
+
+fn load_amount<I: IO>(io: &I, prefix: &[u8], token_id: &AccountId) -> u128 {
+    io.read_storage(&token_key(prefix, token_id))
+        .and_then(|value| value.to_value().ok())
+        .unwrap_or_default()
+}
+
+fn save_amount<I: IO>(io: &mut I, prefix: &[u8], token_id: &AccountId, amount: u128) {
+    let key = token_key(prefix, token_id);
+
+    if amount == 0 {
+        io.remove_storage(&key);
+    } else {
+        io.write_borsh(&key, &amount);
+    }
+}
+
+/// Marker of the forwarding of the token which is in progress. The balance of the token isn't
+/// settled until the forwarding is finished, so another forwarding can't be started meanwhile.
+pub struct ForwardLock;
+
+impl ForwardLock {
+    pub fn is_locked<I: IO>(io: &I, token_id: &AccountId) -> bool {
+        io.read_storage(&token_key(LOCK_STORAGE_PREFIX, token_id))
+            .is_some()
+    }
+
+    pub fn lock<I: IO>(io: &mut I, token_id: &AccountId) {
+        io.write_storage(&token_key(LOCK_STORAGE_PREFIX, token_id), &[1]);
+    }
+
+    pub fn unlock<I: IO>(io: &mut I, token_id: &AccountId) {
+        io.remove_storage(&token_key(LOCK_STORAGE_PREFIX, token_id));
+    }
+}
+
+/// Accounts added by the owner, which could start the forwarding if the caller policy is
+/// `Relayers`. Every relayer is stored under its own key, so the list isn't bounded by the size
+/// of the state.
+pub struct Relayers;
+
+impl Relayers {
+    pub fn contains<I: IO>(io: &I, account_id: &AccountId) -> bool {
+        io.read_storage(&token_key(RELAYER_STORAGE_PREFIX, account_id))
+            .is_some()
+    }
+
+    pub fn add<I: IO>(io: &mut I, account_id: &AccountId) {
+        io.write_storage(&token_key(RELAYER_STORAGE_PREFIX, account_id), &[1]);
+    }
+
+    pub fn remove<I: IO>(io: &mut I, account_id: &AccountId) {
+        io.remove_storage(&token_key(RELAYER_STORAGE_PREFIX, account_id));
+    }
+}
+
+/// Token contracts allowed or denied by the owner. Only the allowed tokens are forwarded if any
+/// token is allowed. The denied tokens are never forwarded. Every token is stored under its own
+/// key, so the lists aren't bounded by the size of the state.
+pub struct TokenList;
+
+impl TokenList {
+    const DENIED: u8 = 0;
+    const ALLOWED: u8 = 1;
+
+    pub fn is_allowed<I: IO>(io: &I, token_id: &AccountId) -> bool {
+        Self::status(io, token_id).map_or_else(
+            || Self::allowed_count(io) == 0,
+            |status| status == Self::ALLOWED,
+        )
+    }
+
+    pub fn allow<I: IO>(io: &mut I, token_id: &AccountId) {
+        Self::set_status(io, token_id, Some(Self::ALLOWED));
+    }
+
+    pub fn deny<I: IO>(io: &mut I, token_id: &AccountId) {
+        Self::set_status(io, token_id, Some(Self::DENIED));
+    }
+
+    /// Removes the token from both lists.
+    pub fn remove<I: IO>(io: &mut I, token_id: &AccountId) {
+        Self::set_status(io, token_id, None);
+    }
+
+    fn status<I: IO>(io: &I, token_id: &AccountId) -> Option<u8> {
+        io.read_storage(&token_key(TOKEN_LIST_STORAGE_PREFIX, token_id))
+            .and_then(|value| value.to_value().ok())
+    }
+
+    fn set_status<I: IO>(io: &mut I, token_id: &AccountId, status: Option<u8>) {
+        let was_allowed = Self::status(io, token_id) == Some(Self::ALLOWED);
+        let is_allowed = status == Some(Self::ALLOWED);
+        let key = token_key(TOKEN_LIST_STORAGE_PREFIX, token_id);
+
+        match status {
+            Some(status) => io.write_borsh(&key, &status),
+            None => io.remove_storage(&key),
+        };
+
+        let count = match (was_allowed, is_allowed) {
+            (false, true) => Self::allowed_count(io).saturating_add(1),
+            (true, false) => Self::allowed_count(io).saturating_sub(1),
+            _ => return,
+        };
+        io.write_borsh(ALLOWED_COUNT_STORAGE_KEY, &count);
+    }
+
+    fn allowed_count<I: IO>(io: &I) -> u32 {
+        io.read_storage(ALLOWED_COUNT_STORAGE_KEY)
+            .and_then(|value| value.to_value().ok())
+            .unwrap_or_default()
+    }
+}
+
+/// Minimum gas of every transfer of the token to the targets. Some tokens and target networks need
+/// more gas than the default, so it could be overridden by the owner.
+pub struct TransferGas;
+
+impl TransferGas {
+    pub fn load<I: IO>(io: &I, token_id: &AccountId) -> u64 {
+        io.read_storage(&token_key(TRANSFER_GAS_STORAGE_PREFIX, token_id))
+            .and_then(|value| value.to_value().ok())
+            .unwrap_or(gas::DEFAULT_TRANSFER_GAS)
+    }
+
+    pub fn save<I: IO>(io: &mut I, token_id: &AccountId, gas: Option<u64>) {
+        let key = token_key(TRANSFER_GAS_STORAGE_PREFIX, token_id);
+
+        match gas {
+            Some(gas) => io.write_borsh(&key, &gas),
+            None => io.remove_storage(&key),
+        };
+    }
+}
+
+/// Fee rate of the token read from the fees contract. While the rate is cached, the fee is
+/// calculated by the forwarder instead of calling `calculate_fees` of the fees contract.
+#[derive(Copy, Clone, BorshSerialize, BorshDeserialize)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug, PartialEq, Eq))]
+pub struct FeeRate {
+    /// The rate in basis points. There is no fee if it's zero.
+    pub rate_bps: u64,
+    /// The block the rate is read from the fees contract again at.
+    pub expires_at: u64,
+}
+
+impl FeeRate {
+    /// Returns the cached rate of the token unless it has expired or the cache is disabled.
+    pub fn load<I: IO + Env>(io: &I, token_id: &AccountId) -> Option<Self> {
+        if FeeCacheTtl::load(io) == 0 {
+            return None;
+        }
+
+        io.read_storage(&token_key(FEE_RATE_STORAGE_PREFIX, token_id))
+            .and_then(|value| value.to_value::<Self>().ok())
+            .filter(|rate| rate.expires_at > io.block_height())
+    }
+
+    /// Caches the rate of the token for the number of blocks set by the owner.
+    pub fn save<I: IO + Env>(io: &mut I, token_id: &AccountId, rate_bps: u64) -> Self {
+        let rate = Self {
+            rate_bps,
+            expires_at: io.block_height().saturating_add(FeeCacheTtl::load(io)),
+        };
+        io.write_borsh(&token_key(FEE_RATE_STORAGE_PREFIX, token_id), &rate);
+        rate
+    }
+
+    /// Returns `true` if the cache is enabled, but the rate of the token isn't cached or has
+    /// expired.
+    pub fn needs_refresh<I: IO + Env>(io: &I, token_id: &AccountId) -> bool {
+        FeeCacheTtl::load(io) > 0 && Self::load(io, token_id).is_none()
+    }
+
+    /// Calculates the fee the same way as `calculate_fees` of the fees contract: the fee is
+    /// never rounded down to zero unless the rate is zero.
+    pub fn fee(&self, amount: u128) -> u128 {
+        if self.rate_bps == 0 {
+            return 0;
+        }
+
+        math::mul_div(amount, u128::from(self.rate_bps), BPS_DENOMINATOR)
+            .unwrap_or(amount)
+            .max(1)
+    }
+}
+
+/// Number of blocks the fee rates read from the fees contract are cached for. The rates aren't
+/// cached, and the fees contract calculates the fee of every forwarding, if it's zero.
+pub struct FeeCacheTtl;
+
+impl FeeCacheTtl {
+    pub fn load<I: IO>(io: &I) -> u64 {
+        io.read_storage(FEE_CACHE_TTL_STORAGE_KEY)
+            .and_then(|value| value.to_value().ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save<I: IO>(io: &mut I, blocks: u64) {
+        if blocks == 0 {
+            io.remove_storage(FEE_CACHE_TTL_STORAGE_KEY);
+        } else {
+            io.write_borsh(FEE_CACHE_TTL_STORAGE_KEY, &blocks);
+        }
+    }
+}
+
+/// Parameters of the override of the transfer gas of the token. The default gas is used again if
+/// the gas is omitted.
+#[derive(BorshSerialize, BorshDeserialize)]
+pub struct TransferGasParams {
+    pub token_id: AccountId,
+    pub gas: Option<u64>,
+}
+
+/// Parameters of `reclaim`: the refunded token and the account it's transferred to.
+#[derive(BorshSerialize, BorshDeserialize)]
+pub struct ReclaimParams {
+    pub token_id: AccountId,
+    pub receiver_id: AccountId,
+}
+
+/// Parameters of `resolve_reclaim_callback`: the reclaimed token and its amount.
+#[derive(BorshSerialize, BorshDeserialize)]
+pub struct ResolveReclaimParams {
+    pub token_id: AccountId,
+    pub amount: u128,
+}
+
+fn token_key(prefix: &[u8], token_id: &AccountId) -> ArrayVec<u8, 80> {
+    let mut key = ArrayVec::new();
+    key.try_extend_from_slice(prefix)
+        .and_then(|()| key.try_extend_from_slice(token_id.as_bytes()))
+        .unwrap_or_default();
+    key
+}
+
+/// Arguments of the fees contract methods: `calculate_split_fees` if the tokens are split
+/// between several addresses and `calculate_fees` otherwise. A NEAR account is passed as the
+/// target network with the zero address.
 pub struct FeesParams<'a> {
     pub amount: u128,
     pub token_id: &'a AccountId,
-    pub target_network: &'a AccountId,
-    pub target_address: Address,
+    pub target: &'a Target,
+}
+
+impl BorshSerialize for FeesParams<'_> {
+    fn serialize<W: io::Write>(&self, writer: &mut W) -> io::Result<()> {
+        self.amount.serialize(writer)?;
+        self.token_id.serialize(writer)?;
+
+        match self.target {
+            Target::Evm { network, targets } if self.target.is_split() => {
+                network.serialize(writer)?;
+                targets.serialize(writer)
+            }
+            Target::Evm { network, targets } => {
+                network.serialize(writer)?;
+                targets.primary().serialize(writer)
+            }
+            Target::Near { account_id } => {
+                account_id.serialize(writer)?;
+                Address([0; 20]).serialize(writer)
+            }
+        }
+    }
 }
 
-#[derive(BorshDeserialize)]
+#[derive(BorshSerialize)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(PartialEq, Debug))]
 pub struct ForwardParams {
     pub token_id: AccountId,
+    /// Amount of tokens to forward. The whole available balance is forwarded if it's omitted.
+    pub amount: Option<u128>,
+}
+
+impl BorshDeserialize for ForwardParams {
+    fn deserialize_reader<R: io::Read>(reader: &mut R) -> io::Result<Self> {
+        let token_id = AccountId::deserialize_reader(reader)?;
+        // The amount could be missing in the input for compatibility with the callers which
+        // pass the token id only.
+        let mut tag = [0u8; 1];
+        let amount = match (reader.read(&mut tag)?, tag[0]) {
+            (0, _) | (1, 0) => None,
+            (1, 1) => Some(u128::deserialize_reader(reader)?),
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "Invalid Option representation",
+                ))
+            }
+        };
+
+        Ok(Self { token_id, amount })
+    }
+}
+
+/// List of tokens which are forwarded at once.
+pub struct ForwardManyParams {
+    pub token_ids: ArrayVec<AccountId, MAX_COMBINED_PROMISES>,
+}
+
+impl BorshDeserialize for ForwardManyParams {
+    fn deserialize_reader<R: io::Read>(reader: &mut R) -> io::Result<Self> {
+        let len = u32::deserialize_reader(reader)? as usize;
+
+        if len > MAX_COMBINED_PROMISES {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Too many tokens to forward",
+            ));
+        }
+
+        let mut token_ids = ArrayVec::new();
+
+        for _ in 0..len {
+            token_ids.push(AccountId::deserialize_reader(reader)?);
+        }
+
+        Ok(Self { token_ids })
+    }
 }
 
 #[derive(BorshSerialize, BorshDeserialize)]
@@ -53,49 +477,301 @@ pub struct FinishForwardParams {
     pub promise_idx: u64,
 }
 
-pub fn ft_transfer_call_args(receiver_id: &AccountId, amount: u128, address: Address) -> Vec<u8> {
-    let mut result = ArrayString::<128>::new();
+#[derive(BorshSerialize, BorshDeserialize)]
+pub struct ResolveFeeParams {
+    pub token_id: AccountId,
+    pub fee: u128,
+    /// Receipt of the forwarding which charged the fee. It's completed with the result of the
+    /// fee transfer and returned by the callback. The claimed fees have no receipt.
+    pub receipt: Option<ForwardReceipt>,
+}
+
+/// Summary of the forwarding returned by its final callback.
+#[derive(Clone, BorshSerialize, BorshDeserialize)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug, PartialEq))]
+pub struct ForwardReceipt {
+    pub token_id: AccountId,
+    /// The forwarded amount including the fee.
+    pub amount: u128,
+    /// The fee charged on the delivered amount.
+    pub fee: u128,
+    /// The amount used by the receivers.
+    pub delivered: u128,
+    /// Indices of the targets and the amounts used by them.
+    pub transfers: BoundedVec<(u8, u128), MAX_TARGETS>,
+    /// The amount which stays on the forwarder and could be forwarded again or reclaimed.
+    pub refunded: u128,
+    /// `true` if the whole amount sent to the receivers is delivered.
+    pub transfer_succeeded: bool,
+    /// `true` if the fee is sent to the fees contract or there is no fee. Otherwise the fee is
+    /// accrued and could be claimed by the fees contract.
+    pub fee_succeeded: bool,
+}
+
+#[derive(BorshSerialize, BorshDeserialize)]
+pub struct ResolveForwardParams {
+    pub amount: u128,
+    pub fee: u128,
+    pub token_id: AccountId,
+    /// Indices of the targets and the amounts sent to them in the order of the transfer promises.
+    pub sent: BoundedVec<(u8, u128), MAX_TARGETS>,
+}
+
+/// Capacity of the JSON arguments of the cross-contract calls.
+const MAX_ARGS_LEN: usize = 512;
+/// Capacity of the receipt, which fits the amounts used by the maximum number of the targets.
+const MAX_RECEIPT_LEN: usize = 1024;
+/// Capacity of the `msg` of `ft_transfer_call` before its escaping.
+const MAX_MSG_LEN: usize = 256;
+
+pub fn ft_transfer_call_args(
+    receiver_id: &AccountId,
+    amount: u128,
+    address: Address,
+    msg_format: &MsgFormat,
+) -> Result<Vec<u8>, ContractError> {
+    let msg = transfer_msg(address, msg_format)?;
+    let mut writer = JsonWriter::<MAX_ARGS_LEN>::new();
+
+    writer.begin_object()?;
+    writer.str_field("receiver_id", receiver_id.as_str())?;
+    writer.amount_field("amount", amount)?;
+    writer.str_field("msg", msg.as_str())?;
+    writer.end_object()?;
+
+    writer.into_vec()
+}
+
+/// Returns the `msg` of `ft_transfer_call` in the format expected by the target network.
+fn transfer_msg(
+    address: Address,
+    msg_format: &MsgFormat,
+) -> Result<ArrayString<MAX_MSG_LEN>, ContractError> {
+    let mut msg = JsonWriter::<MAX_MSG_LEN>::new();
+
+    match msg_format {
+        MsgFormat::Address => msg.hex(&address.0)?,
+        MsgFormat::RelayerFee { relayer_id, fee } => {
+            let mut fee_bytes = [0; 32];
+            fee_bytes[..16].copy_from_slice(&fee.to_le_bytes());
+
+            msg.raw(relayer_id.as_str())?;
+            msg.raw(":")?;
+            msg.hex(&fee_bytes)?;
+            msg.hex(&address.0)?;
+        }
+        MsgFormat::ChainPrefixed { chain } => {
+            msg.raw(chain.as_str())?;
+            msg.raw(":")?;
+            msg.raw(address_to_hex(address).as_str())?;
+        }
+        // The message is a JSON object, so it's escaped as the `msg` string.
+        MsgFormat::Omni { chain, fee } => {
+            let mut recipient = ArrayString::<64>::new();
+            recipient.push_str(chain.as_str());
+            recipient.push(':');
+            recipient.push_str(address_to_hex(address).as_str());
+
+            msg.begin_object()?;
+            msg.str_field("recipient", recipient.as_str())?;
+            msg.amount_field("fee", *fee)?;
+            msg.amount_field("native_token_fee", 0)?;
+            msg.end_object()?;
+        }
+    }
+
+    Ok(msg.finish())
+}
+
+/// Returns the method and the arguments of the transfer of the amount to the target address
+/// with the given index. The tokens are sent to a NEAR account with `ft_transfer`.
+pub fn transfer_args(
+    state: &State,
+    idx: usize,
+    amount: u128,
+) -> Result<(&'static str, Vec<u8>), ContractError> {
+    match &state.target {
+        Target::Evm { network, targets } => Ok((
+            "ft_transfer_call",
+            ft_transfer_call_args(
+                network,
+                amount,
+                targets.as_slice()[idx].address,
+                &state.msg_format,
+            )?,
+        )),
+        Target::Near { account_id } => Ok(("ft_transfer", ft_transfer_args(account_id, amount)?)),
+    }
+}
+
+pub fn ft_transfer_args(receiver_id: &AccountId, amount: u128) -> Result<Vec<u8>, ContractError> {
+    let mut writer = JsonWriter::<MAX_ARGS_LEN>::new();
+
+    writer.begin_object()?;
+    writer.str_field("receiver_id", receiver_id.as_str())?;
+    writer.amount_field("amount", amount)?;
+    writer.end_object()?;
+
+    writer.into_vec()
+}
+
+pub fn ft_balance_args(account_id: &AccountId) -> Result<Vec<u8>, ContractError> {
+    let mut writer = JsonWriter::<MAX_ARGS_LEN>::new();
+
+    writer.begin_object()?;
+    writer.str_field("account_id", account_id.as_str())?;
+    writer.end_object()?;
+
+    writer.into_vec()
+}
+
+pub fn state_json(state: &State) -> Result<ArrayString<512>, ContractError> {
+    let mut writer = JsonWriter::<512>::new();
+
+    writer.begin_object()?;
+    write_target_fields(&mut writer, &state.target)?;
+    writer.str_field("caller_policy", state.caller_policy.as_str())?;
+    writer.str_field("wnear_contract_id", state.wnear_contract_id.as_str())?;
+    writer.str_field("fees_contract_id", state.fees_contract_id.as_str())?;
+    writer.str_field("owner_id", state.owner_id.as_str())?;
+    writer.end_object()?;
+
+    Ok(writer.finish())
+}
+
+/// Returns the primary address and the network of the target or the NEAR account.
+pub fn target_json(target: &Target) -> Result<ArrayString<256>, ContractError> {
+    let mut writer = JsonWriter::<256>::new();
+
+    writer.begin_object()?;
+    write_target_fields(&mut writer, target)?;
+    writer.end_object()?;
+
+    Ok(writer.finish())
+}
+
+/// Returns the receipt of the forwarding to the target.
+/// Returns the receipt as JSON. The receipt of the split forwarding lists the amount used by
+/// every address.
+pub fn receipt_json(
+    receipt: &ForwardReceipt,
+    target: &Target,
+) -> Result<ArrayString<MAX_RECEIPT_LEN>, ContractError> {
+    let mut writer = JsonWriter::<MAX_RECEIPT_LEN>::new();
+
+    writer.begin_object()?;
+    writer.str_field("token_id", receipt.token_id.as_str())?;
+    writer.amount_field("amount", receipt.amount)?;
+    writer.amount_field("fee", receipt.fee)?;
+    writer.amount_field("delivered", receipt.delivered)?;
+    writer.amount_field("refunded", receipt.refunded)?;
+
+    match target {
+        Target::Evm { network, targets } if target.is_split() => {
+            writer.str_field("target_network", network.as_str())?;
+            writer.key("transfers")?;
+            writer.begin_array()?;
+
+            for (idx, delivered) in receipt.transfers.iter() {
+                let address = targets
+                    .as_slice()
+                    .get(usize::from(*idx))
+                    .ok_or(ContractError::InvalidTargets)?
+                    .address;
+                writer.begin_object()?;
+                writer.str_field("target_address", address_to_hex(address).as_str())?;
+                writer.amount_field("delivered", *delivered)?;
+                writer.end_object()?;
+            }
+
+            writer.end_array()?;
+        }
+        _ => write_target_fields(&mut writer, target)?,
+    }
+
+    writer.bool_field("transfer_succeeded", receipt.transfer_succeeded)?;
+    writer.bool_field("fee_succeeded", receipt.fee_succeeded)?;
+    writer.end_object()?;
+
+    Ok(writer.finish())
+}
+
+fn write_target_fields<const N: usize>(
+    writer: &mut JsonWriter<N>,
+    target: &Target,
+) -> Result<(), ContractError> {
+    match target {
+        Target::Evm { network, targets } => {
+            writer.str_field("target_address", address_to_hex(targets.primary()).as_str())?;
+            writer.str_field("target_network", network.as_str())
+        }
+        Target::Near { account_id } => writer.str_field("target_account_id", account_id.as_str()),
+    }
+}
 
-    result.push_str(r#"{"receiver_id":""#);
-    result.push_str(receiver_id.as_str());
-    result.push_str(r#"","amount":""#);
-    result.push_str(amount_to_str(amount).as_str());
-    result.push_str(r#"","msg":""#);
+pub fn targets_json(targets: &Targets) -> Result<ArrayString<512>, ContractError> {
+    let mut writer = JsonWriter::<512>::new();
 
-    let address_hex_iter = hex::BytesToHexIter::new(address.0.iter().copied());
+    writer.begin_array()?;
 
-    for c in address_hex_iter {
-        result.push(c);
+    for target in targets.as_slice() {
+        writer.begin_object()?;
+        writer.str_field("address", address_to_hex(target.address).as_str())?;
+        writer.number_field("weight", u128::from(target.weight))?;
+        writer.end_object()?;
     }
 
-    result.push_str(r#""}"#);
+    writer.end_array()?;
 
-    Vec::try_from(result.as_bytes()).unwrap_or_default()
+    Ok(writer.finish())
 }
 
-pub fn ft_transfer_args(receiver_id: &AccountId, amount: u128) -> Vec<u8> {
-    let mut result = ArrayString::<128>::new();
+pub fn msg_format_json(msg_format: &MsgFormat) -> Result<ArrayString<256>, ContractError> {
+    let mut writer = JsonWriter::<256>::new();
+
+    writer.begin_object()?;
+
+    match msg_format {
+        MsgFormat::Address => writer.str_field("format", "address")?,
+        MsgFormat::RelayerFee { relayer_id, fee } => {
+            writer.str_field("format", "relayer_fee")?;
+            writer.str_field("relayer_id", relayer_id.as_str())?;
+            writer.amount_field("fee", *fee)?;
+        }
+        MsgFormat::ChainPrefixed { chain } => {
+            writer.str_field("format", "chain_prefixed")?;
+            writer.str_field("chain", chain.as_str())?;
+        }
+        MsgFormat::Omni { chain, fee } => {
+            writer.str_field("format", "omni")?;
+            writer.str_field("chain", chain.as_str())?;
+            writer.amount_field("fee", *fee)?;
+        }
+    }
+
+    writer.end_object()?;
+
+    Ok(writer.finish())
+}
 
-    result.push_str(r#"{"receiver_id":""#);
-    result.push_str(receiver_id.as_str());
-    result.push_str(r#"","amount":""#);
-    result.push_str(amount_to_str(amount).as_str());
-    result.push_str(r#""}"#);
+pub fn json_string(value: &str) -> Result<ArrayString<128>, ContractError> {
+    let mut writer = JsonWriter::<128>::new();
+    writer.string(value)?;
 
-    Vec::try_from(result.as_bytes()).unwrap_or_default()
+    Ok(writer.finish())
 }
 
-pub fn ft_balance_args(account_id: &AccountId) -> Vec<u8> {
-    let mut result = ArrayString::<128>::new();
+/// Returns the `0x` prefixed hex representation of the address.
+pub fn address_to_hex(address: Address) -> ArrayString<42> {
+    let mut result = ArrayString::<42>::new();
 
-    result.push_str(r#"{"account_id":""#);
-    result.push_str(account_id.as_str());
-    result.push_str(r#""}"#);
+    result.push_str("0x");
+    hex::BytesToHexIter::new(address.0.iter().copied()).for_each(|c| result.push(c));
 
-    Vec::try_from(result.as_bytes()).unwrap_or_default()
+    result
 }
 
-fn amount_to_str(mut amount: u128) -> ArrayString<39> {
+pub fn amount_to_str(mut amount: u128) -> ArrayString<39> {
     let mut len = 0;
     let mut buf = ['0'; 39];
 
@@ -118,42 +794,133 @@ fn amount_to_str(mut amount: u128) -> ArrayString<39> {
     result
 }
 
-pub fn vec_to_number<T: FromStr>(vec: &Vec<u8>) -> Result<T, ContractError> {
-    let x = core::str::from_utf8(&vec[..]).map_err(|_| ContractError::BadUtf8String)?;
-    T::from_str(x.trim_matches('"')).map_err(|_| ContractError::BadNumber)
+/// Returns the `amount` from the arguments of the NEP-141 `ft_on_transfer` call.
+pub fn ft_on_transfer_amount(args: &[u8]) -> Result<u128, ContractError> {
+    JsonReader::field(args, "amount")
+        .map_err(|_| ContractError::BadNumber)?
+        .read_amount()
 }
 
 #[test]
-fn test_deserialize_state() {
-    let original = State {
+fn test_deserialize_init_params() {
+    let state = || LegacyState {
         target_address: Address([1; 20]),
         target_network: AccountId::new("target.near").unwrap(),
         wnear_contract_id: AccountId::new("wnear.near").unwrap(),
         fees_contract_id: AccountId::new("fees.near").unwrap(),
         owner_id: AccountId::new("owner.near").unwrap(),
     };
+    let bytes = crate::types::to_borsh(&state()).unwrap();
+
+    assert_eq!(
+        InitParams::try_from_slice(&bytes).unwrap(),
+        InitParams {
+            state: state().into(),
+            add_full_access_key: true,
+        }
+    );
+
+    let bytes = crate::types::to_borsh(&(state(), false)).unwrap();
+
+    assert_eq!(
+        InitParams::try_from_slice(&bytes).unwrap(),
+        InitParams {
+            state: state().into(),
+            add_full_access_key: false,
+        }
+    );
+
+    let account_id = AccountId::new("exchange.near").unwrap();
+    let bytes = crate::types::to_borsh(&(state(), false, Some(account_id))).unwrap();
+
+    assert_eq!(
+        InitParams::try_from_slice(&bytes).unwrap(),
+        InitParams {
+            state: State {
+                target: Target::Near { account_id },
+                ..state().into()
+            },
+            add_full_access_key: false,
+        }
+    );
+
+    let bytes = crate::types::to_borsh(&(state(), true, None::<AccountId>, 500u16)).unwrap();
+
+    assert_eq!(
+        InitParams::try_from_slice(&bytes).unwrap(),
+        InitParams {
+            state: State {
+                max_fee_bps: 500,
+                ..state().into()
+            },
+            add_full_access_key: true,
+        }
+    );
+
+    let bytes = crate::types::to_borsh(&(state(), 2u8)).unwrap();
+
+    assert!(InitParams::try_from_slice(&bytes).is_err());
+
+    let bytes =
+        crate::types::to_borsh(&(state(), true, None::<AccountId>, MAX_FEE_BPS + 1)).unwrap();
+
+    assert!(InitParams::try_from_slice(&bytes).is_err());
+}
+
+#[test]
+fn test_deserialize_forward_params() {
+    let token_id = AccountId::new("token.near").unwrap();
+    let bytes = crate::types::to_borsh(&token_id).unwrap();
+
+    assert_eq!(
+        ForwardParams::try_from_slice(&bytes).unwrap(),
+        ForwardParams {
+            token_id,
+            amount: None
+        }
+    );
 
+    let original = ForwardParams {
+        token_id,
+        amount: Some(42),
+    };
     let bytes = crate::types::to_borsh(&original).unwrap();
-    let expected = State::try_from_slice(bytes.as_slice()).unwrap();
 
-    assert_eq!(original, expected);
+    assert_eq!(ForwardParams::try_from_slice(&bytes).unwrap(), original);
+}
+
+#[test]
+fn test_deserialize_forward_many_params() {
+    let token_ids = [
+        AccountId::new("usdt.near").unwrap(),
+        AccountId::new("near").unwrap(),
+    ];
+    let bytes = crate::types::to_borsh(token_ids.as_slice()).unwrap();
+    let params = ForwardManyParams::try_from_slice(&bytes).unwrap();
+
+    assert_eq!(params.token_ids.as_slice(), token_ids.as_slice());
+
+    let token_ids = [AccountId::new("usdt.near").unwrap(); MAX_COMBINED_PROMISES + 1];
+    let bytes = crate::types::to_borsh(token_ids.as_slice()).unwrap();
+
+    assert!(ForwardManyParams::try_from_slice(&bytes).is_err());
 }
 
 #[test]
 fn test_ft_balance_args() {
-    let json = ft_balance_args(&AccountId::new("test.near").unwrap());
+    let json = ft_balance_args(&AccountId::new("test.near").unwrap()).unwrap();
     assert_eq!(&json[..], br#"{"account_id":"test.near"}"#);
 }
 
 #[test]
 fn test_ft_transfer_args() {
-    let json = ft_transfer_args(&AccountId::new("test.near").unwrap(), 12_345_670);
+    let json = ft_transfer_args(&AccountId::new("test.near").unwrap(), 12_345_670).unwrap();
     assert_eq!(
         &json[..],
         br#"{"receiver_id":"test.near","amount":"12345670"}"#
     );
 
-    let json = ft_transfer_args(&AccountId::new("test.near").unwrap(), 0);
+    let json = ft_transfer_args(&AccountId::new("test.near").unwrap(), 0).unwrap();
     assert_eq!(&json[..], br#"{"receiver_id":"test.near","amount":"0"}"#);
 }
 
@@ -165,10 +932,434 @@ fn test_ft_transfer_call_args() {
         &AccountId::new("test.near").unwrap(),
         12_345_670,
         Address(address),
-    );
+        &MsgFormat::Address,
+    )
+    .unwrap();
     assert_eq!(&json[..], br#"{"receiver_id":"test.near","amount":"12345670","msg":"7e5f4552091a69125d5dfcb7b8c2659029395bdf"}"#);
 }
 
+#[test]
+fn test_ft_transfer_call_args_with_relayer_fee() {
+    use hex::FromHex;
+    let address = <[u8; 20]>::from_hex("7e5f4552091a69125d5dfcb7b8c2659029395bdf").unwrap();
+    let json = ft_transfer_call_args(
+        &AccountId::new("silo.aurora").unwrap(),
+        12_345_670,
+        Address(address),
+        &MsgFormat::RelayerFee {
+            relayer_id: AccountId::new("relayer.near").unwrap(),
+            fee: 0x0102,
+        },
+    )
+    .unwrap();
+    assert_eq!(&json[..], br#"{"receiver_id":"silo.aurora","amount":"12345670","msg":"relayer.near:02010000000000000000000000000000000000000000000000000000000000007e5f4552091a69125d5dfcb7b8c2659029395bdf"}"#);
+}
+
+#[test]
+fn test_ft_transfer_call_args_with_chain_prefix() {
+    use hex::FromHex;
+    let address = <[u8; 20]>::from_hex("7e5f4552091a69125d5dfcb7b8c2659029395bdf").unwrap();
+    let json = ft_transfer_call_args(
+        &AccountId::new("locker.bridge.near").unwrap(),
+        12_345_670,
+        Address(address),
+        &MsgFormat::ChainPrefixed {
+            chain: ChainId::new("eth").unwrap(),
+        },
+    )
+    .unwrap();
+    assert_eq!(&json[..], br#"{"receiver_id":"locker.bridge.near","amount":"12345670","msg":"eth:0x7e5f4552091a69125d5dfcb7b8c2659029395bdf"}"#);
+}
+
+#[test]
+fn test_ft_transfer_call_args_for_omni_bridge() {
+    use hex::FromHex;
+    let address = <[u8; 20]>::from_hex("7e5f4552091a69125d5dfcb7b8c2659029395bdf").unwrap();
+    let json = ft_transfer_call_args(
+        &AccountId::new("omni.bridge.near").unwrap(),
+        12_345_670,
+        Address(address),
+        &MsgFormat::Omni {
+            chain: ChainId::new("base").unwrap(),
+            fee: 100,
+        },
+    )
+    .unwrap();
+    assert_eq!(&json[..], br#"{"receiver_id":"omni.bridge.near","amount":"12345670","msg":"{\"recipient\":\"base:0x7e5f4552091a69125d5dfcb7b8c2659029395bdf\",\"fee\":\"100\",\"native_token_fee\":\"0\"}"}"#);
+}
+
+#[test]
+fn test_ft_transfer_call_args_fit_the_buffer() {
+    let long_account_id = AccountId::new(&"a".repeat(64)).unwrap();
+    let msg_formats = [
+        MsgFormat::Address,
+        MsgFormat::RelayerFee {
+            relayer_id: long_account_id,
+            fee: u128::MAX,
+        },
+        MsgFormat::ChainPrefixed {
+            chain: ChainId::new(&"c".repeat(16)).unwrap(),
+        },
+        MsgFormat::Omni {
+            chain: ChainId::new(&"c".repeat(16)).unwrap(),
+            fee: u128::MAX,
+        },
+    ];
+
+    for msg_format in &msg_formats {
+        let json =
+            ft_transfer_call_args(&long_account_id, u128::MAX, Address([0xff; 20]), msg_format)
+                .unwrap();
+        assert!(json.ends_with(br#""}"#));
+        assert_eq!(
+            JsonReader::field(&json, "receiver_id").and_then(|mut r| r.read_str()),
+            Ok(long_account_id.as_str())
+        );
+        assert_eq!(
+            JsonReader::field(&json, "amount").and_then(|mut r| r.read_amount()),
+            Ok(u128::MAX)
+        );
+    }
+}
+
+#[test]
+fn test_ft_args_fit_the_buffer() {
+    let long_account_id = AccountId::new(&"a".repeat(64)).unwrap();
+    let json = ft_transfer_args(&long_account_id, u128::MAX).unwrap();
+
+    assert_eq!(
+        json.as_slice(),
+        format!(
+            r#"{{"receiver_id":"{}","amount":"{}"}}"#,
+            long_account_id.as_str(),
+            u128::MAX
+        )
+        .as_bytes()
+    );
+
+    let json = ft_balance_args(&long_account_id).unwrap();
+    assert_eq!(
+        json.as_slice(),
+        format!(r#"{{"account_id":"{}"}}"#, long_account_id.as_str()).as_bytes()
+    );
+}
+
+#[test]
+fn test_msg_format_json() {
+    assert_eq!(
+        msg_format_json(&MsgFormat::Address).unwrap().as_str(),
+        r#"{"format":"address"}"#
+    );
+    assert_eq!(
+        msg_format_json(&MsgFormat::RelayerFee {
+            relayer_id: AccountId::new("relayer.near").unwrap(),
+            fee: 10,
+        })
+        .unwrap()
+        .as_str(),
+        r#"{"format":"relayer_fee","relayer_id":"relayer.near","fee":"10"}"#
+    );
+    assert_eq!(
+        msg_format_json(&MsgFormat::ChainPrefixed {
+            chain: ChainId::new("eth").unwrap(),
+        })
+        .unwrap()
+        .as_str(),
+        r#"{"format":"chain_prefixed","chain":"eth"}"#
+    );
+    assert_eq!(
+        msg_format_json(&MsgFormat::Omni {
+            chain: ChainId::new("eth").unwrap(),
+            fee: 10,
+        })
+        .unwrap()
+        .as_str(),
+        r#"{"format":"omni","chain":"eth","fee":"10"}"#
+    );
+}
+
+#[test]
+fn test_state_json() {
+    let targets = Targets::single(Address([1; 20]));
+    let state = State {
+        target: Target::Evm {
+            network: AccountId::new("target.near").unwrap(),
+            targets: targets.clone(),
+        },
+        msg_format: MsgFormat::Address,
+        reserve_margin: 0,
+        max_fee_bps: 0,
+        caller_policy: CallerPolicy::Anyone,
+        wnear_contract_id: AccountId::new("wnear.near").unwrap(),
+        fees_contract_id: AccountId::new("fees.near").unwrap(),
+        owner_id: AccountId::new("owner.near").unwrap(),
+    };
+
+    assert_eq!(
+        state_json(&state).unwrap().as_str(),
+        r#"{"target_address":"0x0101010101010101010101010101010101010101","target_network":"target.near","caller_policy":"anyone","wnear_contract_id":"wnear.near","fees_contract_id":"fees.near","owner_id":"owner.near"}"#
+    );
+    assert_eq!(
+        target_json(&state.target).unwrap().as_str(),
+        r#"{"target_address":"0x0101010101010101010101010101010101010101","target_network":"target.near"}"#
+    );
+    assert_eq!(
+        json_string(address_to_hex(targets.primary()).as_str())
+            .unwrap()
+            .as_str(),
+        r#""0x0101010101010101010101010101010101010101""#
+    );
+    assert_eq!(
+        targets_json(&targets).unwrap().as_str(),
+        r#"[{"address":"0x0101010101010101010101010101010101010101","weight":1}]"#
+    );
+
+    let state = State {
+        target: Target::Near {
+            account_id: AccountId::new("exchange.near").unwrap(),
+        },
+        ..state
+    };
+
+    assert_eq!(
+        state_json(&state).unwrap().as_str(),
+        r#"{"target_account_id":"exchange.near","caller_policy":"anyone","wnear_contract_id":"wnear.near","fees_contract_id":"fees.near","owner_id":"owner.near"}"#
+    );
+    assert_eq!(
+        target_json(&state.target).unwrap().as_str(),
+        r#"{"target_account_id":"exchange.near"}"#
+    );
+    // The state with the longest accounts fits the buffer.
+    let account_id = |c: &str| AccountId::new(&c.repeat(64)).unwrap();
+    let state = State {
+        target: Target::Evm {
+            network: account_id("a"),
+            targets: Targets::single(Address([1; 20])),
+        },
+        caller_policy: CallerPolicy::Relayers,
+        wnear_contract_id: account_id("b"),
+        fees_contract_id: account_id("c"),
+        owner_id: account_id("d"),
+        ..state
+    };
+
+    assert!(state_json(&state).is_ok());
+}
+
+#[test]
+fn test_transfer_args() {
+    use crate::state::WeightedAddress;
+
+    let targets = Targets::new(
+        [
+            WeightedAddress {
+                address: Address([1; 20]),
+                weight: 1,
+            },
+            WeightedAddress {
+                address: Address([2; 20]),
+                weight: 1,
+            },
+        ]
+        .into_iter()
+        .collect::<ArrayVec<_, MAX_TARGETS>>()
+        .into(),
+    )
+    .unwrap();
+    let state = State {
+        target: Target::Evm {
+            network: AccountId::new("aurora").unwrap(),
+            targets,
+        },
+        msg_format: MsgFormat::Address,
+        reserve_margin: 0,
+        max_fee_bps: 0,
+        caller_policy: CallerPolicy::Anyone,
+        wnear_contract_id: AccountId::new("wnear.near").unwrap(),
+        fees_contract_id: AccountId::new("fees.near").unwrap(),
+        owner_id: AccountId::new("owner.near").unwrap(),
+    };
+    let (method, args) = transfer_args(&state, 1, 100).unwrap();
+
+    assert_eq!(method, "ft_transfer_call");
+    assert_eq!(
+        &args[..],
+        br#"{"receiver_id":"aurora","amount":"100","msg":"0202020202020202020202020202020202020202"}"#
+    );
+
+    let state = State {
+        target: Target::Near {
+            account_id: AccountId::new("exchange.near").unwrap(),
+        },
+        ..state
+    };
+    let (method, args) = transfer_args(&state, 0, 100).unwrap();
+
+    assert_eq!(method, "ft_transfer");
+    assert_eq!(
+        &args[..],
+        br#"{"receiver_id":"exchange.near","amount":"100"}"#
+    );
+}
+
+#[test]
+fn test_serialize_fees_params() {
+    use crate::state::WeightedAddress;
+
+    let token_id = AccountId::new("usdt.near").unwrap();
+    let network = AccountId::new("aurora").unwrap();
+    let targets = Targets::single(Address([1; 20]));
+    let target = Target::Evm { network, targets };
+    let params = FeesParams {
+        amount: 100,
+        token_id: &token_id,
+        target: &target,
+    };
+
+    // The single target is passed to `calculate_fees`.
+    assert_eq!(
+        crate::types::to_borsh(&params).unwrap(),
+        crate::types::to_borsh(&(100u128, token_id, network, Address([1; 20]))).unwrap()
+    );
+
+    let targets = Targets::new(
+        [
+            WeightedAddress {
+                address: Address([1; 20]),
+                weight: 1,
+            },
+            WeightedAddress {
+                address: Address([2; 20]),
+                weight: 3,
+            },
+        ]
+        .into_iter()
+        .collect::<ArrayVec<_, MAX_TARGETS>>()
+        .into(),
+    )
+    .unwrap();
+    let target = Target::Evm {
+        network,
+        targets: targets.clone(),
+    };
+    let params = FeesParams {
+        amount: 100,
+        token_id: &token_id,
+        target: &target,
+    };
+
+    assert_eq!(
+        crate::types::to_borsh(&params).unwrap(),
+        crate::types::to_borsh(&(100u128, token_id, network, targets)).unwrap()
+    );
+
+    let account_id = AccountId::new("exchange.near").unwrap();
+    let target = Target::Near { account_id };
+    let params = FeesParams {
+        amount: 100,
+        token_id: &token_id,
+        target: &target,
+    };
+
+    assert_eq!(
+        crate::types::to_borsh(&params).unwrap(),
+        crate::types::to_borsh(&(100u128, token_id, account_id, Address([0; 20]))).unwrap()
+    );
+}
+
+#[test]
+fn test_receipt_json() {
+    use crate::state::WeightedAddress;
+
+    let receipt = ForwardReceipt {
+        token_id: AccountId::new("usdt.near").unwrap(),
+        amount: 1000,
+        fee: 50,
+        delivered: 950,
+        transfers: ArrayVec::try_from([(0, 950)].as_slice()).unwrap().into(),
+        refunded: 0,
+        transfer_succeeded: true,
+        fee_succeeded: false,
+    };
+    let target = Target::Evm {
+        network: AccountId::new("aurora").unwrap(),
+        targets: Targets::single(Address([1; 20])),
+    };
+
+    assert_eq!(
+        receipt_json(&receipt, &target).unwrap().as_str(),
+        r#"{"token_id":"usdt.near","amount":"1000","fee":"50","delivered":"950","refunded":"0","target_address":"0x0101010101010101010101010101010101010101","target_network":"aurora","transfer_succeeded":true,"fee_succeeded":false}"#
+    );
+
+    let target = Target::Near {
+        account_id: AccountId::new("exchange.near").unwrap(),
+    };
+    let json = receipt_json(&receipt, &target).unwrap();
+
+    assert!(json.contains(r#""target_account_id":"exchange.near""#));
+
+    // The split receipt lists the amount used by every address.
+    let split_targets = |count: u8| {
+        (1..=count)
+            .map(|i| WeightedAddress {
+                address: Address([i; 20]),
+                weight: 1,
+            })
+            .collect::<ArrayVec<_, MAX_TARGETS>>()
+            .into()
+    };
+    let receipt = ForwardReceipt {
+        delivered: 700,
+        transfers: ArrayVec::try_from([(0, 475), (1, 225)].as_slice())
+            .unwrap()
+            .into(),
+        refunded: 250,
+        transfer_succeeded: false,
+        ..receipt
+    };
+    let target = Target::Evm {
+        network: AccountId::new("aurora").unwrap(),
+        targets: Targets::new(split_targets(2)).unwrap(),
+    };
+
+    assert_eq!(
+        receipt_json(&receipt, &target).unwrap().as_str(),
+        r#"{"token_id":"usdt.near","amount":"1000","fee":"50","delivered":"700","refunded":"250","target_network":"aurora","transfers":[{"target_address":"0x0101010101010101010101010101010101010101","delivered":"475"},{"target_address":"0x0202020202020202020202020202020202020202","delivered":"225"}],"transfer_succeeded":false,"fee_succeeded":false}"#
+    );
+
+    // The longest receipt fits the buffer.
+    let receipt = ForwardReceipt {
+        token_id: AccountId::new(&"a".repeat(64)).unwrap(),
+        amount: u128::MAX,
+        fee: u128::MAX,
+        delivered: u128::MAX,
+        transfers: ArrayVec::from([(0, u128::MAX); MAX_TARGETS]).into(),
+        refunded: u128::MAX,
+        ..receipt
+    };
+    let target = Target::Evm {
+        network: AccountId::new(&"b".repeat(64)).unwrap(),
+        targets: Targets::new(split_targets(4)).unwrap(),
+    };
+
+    assert!(receipt_json(&receipt, &target).is_ok());
+}
+
+#[test]
+fn test_fee_rate() {
+    let rate = |rate_bps| FeeRate {
+        rate_bps,
+        expires_at: 0,
+    };
+
+    assert_eq!(rate(500).fee(1000), 50);
+    assert_eq!(rate(25).fee(1000), 2);
+    // The fee is never rounded down to zero unless there is no fee.
+    assert_eq!(rate(500).fee(1), 1);
+    assert_eq!(rate(0).fee(1000), 0);
+    assert_eq!(rate(1000).fee(u128::MAX), u128::MAX / 10);
+}
+
 #[test]
 fn test_amount_to_str() {
     assert_eq!(amount_to_str(0).as_str(), "0");
@@ -180,17 +1371,21 @@ fn test_amount_to_str() {
 }
 
 #[test]
-fn test_vec_to_number() {
+fn test_ft_on_transfer_amount() {
     assert_eq!(
-        vec_to_number(&Vec::try_from(b"42".as_slice()).unwrap()),
-        Ok(42)
+        ft_on_transfer_amount(br#"{"sender_id":"alice.near","amount":"12345","msg":""}"#),
+        Ok(12345)
     );
     assert_eq!(
-        vec_to_number::<u32>(&Vec::try_from(b"4\xc3\x28".as_slice()).unwrap()),
-        Err(ContractError::BadUtf8String)
+        ft_on_transfer_amount(br#"{ "amount" : "1", "sender_id": "alice.near", "msg": "" }"#),
+        Ok(1)
+    );
+    assert_eq!(
+        ft_on_transfer_amount(br#"{"sender_id":"alice.near","amount":12345,"msg":""}"#),
+        Err(ContractError::BadNumber)
     );
     assert_eq!(
-        vec_to_number::<u32>(&Vec::try_from(b"4x".as_slice()).unwrap()),
+        ft_on_transfer_amount(br#"{"sender_id":"alice.near","msg":""}"#),
         Err(ContractError::BadNumber)
     );
 }
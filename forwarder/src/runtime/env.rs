@@ -16,6 +16,19 @@ pub trait Env {
     fn attached_deposit(&self) -> u128;
     /// Account's balance in yoctoNEAR.
     fn account_balance(&self) -> u128;
+    /// Number of bytes used by the account, which are covered by the storage staking.
+    fn storage_usage(&self) -> u64;
+    /// Height of the current block.
+    fn block_height(&self) -> u64;
+    /// Amount of gas attached to the current call.
+    fn prepaid_gas(&self) -> u64;
+    /// Amount of gas burnt so far by the current call.
+    fn used_gas(&self) -> u64;
+
+    /// Amount of gas which is still available for the current call.
+    fn remaining_gas(&self) -> u64 {
+        self.prepaid_gas().saturating_sub(self.used_gas())
+    }
 
     fn assert_private_call(&self) -> Result<(), ContractError> {
         if self.predecessor_account_id() == self.current_account_id() {
@@ -34,6 +47,40 @@ pub trait Env {
     }
 }
 
+impl<T: Env> Env for &mut T {
+    fn current_account_id(&self) -> AccountId {
+        (**self).current_account_id()
+    }
+
+    fn predecessor_account_id(&self) -> AccountId {
+        (**self).predecessor_account_id()
+    }
+
+    fn attached_deposit(&self) -> u128 {
+        (**self).attached_deposit()
+    }
+
+    fn account_balance(&self) -> u128 {
+        (**self).account_balance()
+    }
+
+    fn storage_usage(&self) -> u64 {
+        (**self).storage_usage()
+    }
+
+    fn block_height(&self) -> u64 {
+        (**self).block_height()
+    }
+
+    fn prepaid_gas(&self) -> u64 {
+        (**self).prepaid_gas()
+    }
+
+    fn used_gas(&self) -> u64 {
+        (**self).used_gas()
+    }
+}
+
 impl Env for Runtime {
     fn current_account_id(&self) -> AccountId {
         unsafe {
@@ -64,4 +111,20 @@ impl Env for Runtime {
             u128::from_le_bytes(data)
         }
     }
+
+    fn storage_usage(&self) -> u64 {
+        unsafe { exports::storage_usage() }
+    }
+
+    fn block_height(&self) -> u64 {
+        unsafe { exports::block_index() }
+    }
+
+    fn prepaid_gas(&self) -> u64 {
+        unsafe { exports::prepaid_gas() }
+    }
+
+    fn used_gas(&self) -> u64 {
+        unsafe { exports::used_gas() }
+    }
 }
@@ -3,6 +3,9 @@ use crate::runtime::sys::exports;
 use crate::runtime::Runtime;
 use crate::types::{PromiseBatchAction, PromiseCreateArgs, PromiseResult, PromiseWithCallbackArgs};
 
+/// Maximum number of promises which could be combined into one.
+pub const MAX_COMBINED_PROMISES: usize = 8;
+
 #[derive(Debug, Eq, PartialEq, Copy, Clone)]
 pub struct PromiseId(u64);
 
@@ -19,6 +22,8 @@ impl PromiseId {
 }
 
 pub trait PromiseHandler {
+    /// Number of the results of the promises the callback is attached to.
+    fn promise_results_count(&self) -> u64;
     fn promise_result(&self, index: u64) -> Option<PromiseResult>;
 
     /// # Safety
@@ -29,10 +34,22 @@ pub trait PromiseHandler {
     /// arbitrary calls using the Engine.
     unsafe fn promise_create_call(&mut self, args: &PromiseCreateArgs) -> PromiseId;
 
-    /// Combine more than one promise into one.
+    /// Combine already created promises into one. The number of promises must not exceed
+    /// `MAX_COMBINED_PROMISES`.
+    fn promise_and(&mut self, promises: &[PromiseId]) -> PromiseId;
+
+    /// Combine more than one promise into one. The number of promises must not exceed
+    /// `MAX_COMBINED_PROMISES`.
     /// # Safety
     /// Safe because of use `promise_create_call` function under the hood.
-    unsafe fn promise_create_and_combine(&mut self, args: &[PromiseCreateArgs]) -> PromiseId;
+    unsafe fn promise_create_and_combine(&mut self, args: &[PromiseCreateArgs]) -> PromiseId {
+        let ids = args
+            .iter()
+            .map(|args| self.promise_create_call(args))
+            .collect::<arrayvec::ArrayVec<_, MAX_COMBINED_PROMISES>>();
+
+        self.promise_and(&ids)
+    }
 
     /// # Safety
     /// See note on `promise_create_call`.
@@ -48,7 +65,7 @@ pub trait PromiseHandler {
     /// code or adding/removing access keys.
     unsafe fn promise_create_batch<const S: usize>(
         &mut self,
-        args: &PromiseBatchAction<S>,
+        args: &PromiseBatchAction<'_, S>,
     ) -> PromiseId;
 
     fn promise_return(&mut self, promise: PromiseId);
@@ -61,15 +78,62 @@ pub trait PromiseHandler {
     }
 }
 
+impl<T: PromiseHandler> PromiseHandler for &mut T {
+    fn promise_results_count(&self) -> u64 {
+        (**self).promise_results_count()
+    }
+
+    fn promise_result(&self, index: u64) -> Option<PromiseResult> {
+        (**self).promise_result(index)
+    }
+
+    unsafe fn promise_create_call(&mut self, args: &PromiseCreateArgs) -> PromiseId {
+        (**self).promise_create_call(args)
+    }
+
+    fn promise_and(&mut self, promises: &[PromiseId]) -> PromiseId {
+        (**self).promise_and(promises)
+    }
+
+    unsafe fn promise_attach_callback(
+        &mut self,
+        base: PromiseId,
+        callback: &PromiseCreateArgs,
+    ) -> PromiseId {
+        (**self).promise_attach_callback(base, callback)
+    }
+
+    unsafe fn promise_create_batch<const S: usize>(
+        &mut self,
+        args: &PromiseBatchAction<'_, S>,
+    ) -> PromiseId {
+        (**self).promise_create_batch(args)
+    }
+
+    fn promise_return(&mut self, promise: PromiseId) {
+        (**self).promise_return(promise);
+    }
+}
+
 impl PromiseHandler for Runtime {
+    fn promise_results_count(&self) -> u64 {
+        unsafe { exports::promise_results_count() }
+    }
+
     fn promise_result(&self, index: u64) -> Option<PromiseResult> {
         unsafe {
             match exports::promise_result(index, Self::PROMISE_REGISTER_ID.0) {
                 0 => Some(PromiseResult::NotReady),
-                1 => {
-                    let bytes = Self::PROMISE_REGISTER_ID.to_vec();
-                    Some(PromiseResult::Successful(bytes))
-                }
+                1 => Some(Self::PROMISE_REGISTER_ID.to_vec().map_or_else(
+                    |_| {
+                        PromiseResult::TooLarge(
+                            Self::PROMISE_REGISTER_ID
+                                .to_prefix_vec()
+                                .unwrap_or_default(),
+                        )
+                    },
+                    PromiseResult::Successful,
+                )),
                 2 => Some(PromiseResult::Failed),
                 _ => None,
             }
@@ -98,12 +162,12 @@ impl PromiseHandler for Runtime {
         PromiseId::new(id)
     }
 
-    unsafe fn promise_create_and_combine(&mut self, args: &[PromiseCreateArgs]) -> PromiseId {
-        let ids = args
+    fn promise_and(&mut self, promises: &[PromiseId]) -> PromiseId {
+        let ids = promises
             .iter()
-            .map(|args| self.promise_create_call(args))
-            .collect::<arrayvec::ArrayVec<_, 2>>();
-        let id = exports::promise_and(ids.as_ptr() as _, ids.len() as _);
+            .map(|id| id.raw())
+            .collect::<arrayvec::ArrayVec<_, MAX_COMBINED_PROMISES>>();
+        let id = unsafe { exports::promise_and(ids.as_ptr() as _, ids.len() as _) };
 
         PromiseId::new(id)
     }
@@ -138,7 +202,7 @@ impl PromiseHandler for Runtime {
 
     unsafe fn promise_create_batch<const S: usize>(
         &mut self,
-        args: &PromiseBatchAction<S>,
+        args: &PromiseBatchAction<'_, S>,
     ) -> PromiseId {
         let account_id = args.target_account_id.as_bytes();
 
@@ -8,19 +8,37 @@ pub trait StorageIntermediate: Sized {
     fn len(&self) -> usize;
     fn copy_to_slice(&self, buffer: &mut [u8]);
 
-    fn to_vec(&self) -> Vec<u8> {
+    /// Copies the beginning of the value which fits the buffer and returns the number of the
+    /// copied bytes. Fails with `ERR_RESULT_TOO_LARGE` if the value can't be read at all.
+    fn read_prefix(&self, buffer: &mut [u8]) -> Result<usize, ContractError>;
+
+    /// Copies the whole value. Fails with `ERR_RESULT_TOO_LARGE` if it doesn't fit `Vec`.
+    fn to_vec(&self) -> Result<Vec<u8>, ContractError> {
         let len = self.len();
         let mut buf = Vec::new();
-        assert!(len <= buf.capacity());
+
+        if len > buf.capacity() {
+            return Err(ContractError::ResultTooLarge);
+        }
+
         unsafe {
             buf.set_len(len);
         }
         self.copy_to_slice(&mut buf[..len]);
-        buf
+        Ok(buf)
+    }
+
+    /// Copies the beginning of the value which fits `Vec`.
+    fn to_prefix_vec(&self) -> Result<Vec<u8>, ContractError> {
+        let mut buf = Vec::new();
+        buf.extend(core::iter::repeat_n(0, buf.capacity()));
+        let len = self.read_prefix(&mut buf)?;
+        buf.truncate(len);
+        Ok(buf)
     }
 
     fn to_value<T: BorshDeserialize>(&self) -> Result<T, ContractError> {
-        let bytes = self.to_vec();
+        let bytes = self.to_vec()?;
         T::try_from_slice(&bytes[..]).map_err(|_| ContractError::BorshDeserializeError)
     }
 }
@@ -37,6 +55,10 @@ pub trait IO {
     /// on NEAR these would be the arguments to the method.
     fn read_input(&self) -> Self::StorageValue;
 
+    /// Return a value to an external process. In the case of wasm contracts on NEAR
+    /// this corresponds to the value returned from the method.
+    fn return_output(&mut self, value: &[u8]);
+
     /// Read the value in storage at the given key, if any.
     fn read_storage(&self, key: &[u8]) -> Option<Self::StorageValue>;
 
@@ -44,6 +66,9 @@ pub trait IO {
     /// value stored at that key (if any).
     fn write_storage(&mut self, key: &[u8], value: &[u8]) -> Option<Self::StorageValue>;
 
+    /// Remove entry from storage and capture the value present at the given key (if any)
+    fn remove_storage(&mut self, key: &[u8]) -> Option<Self::StorageValue>;
+
     /// Convenience function to read the input and deserialize the bytes using borsh.
     fn read_input_borsh<U: BorshDeserialize>(&self) -> Result<U, ContractError> {
         self.read_input().to_value()
@@ -59,6 +84,30 @@ pub trait IO {
     }
 }
 
+impl<T: IO> IO for &mut T {
+    type StorageValue = T::StorageValue;
+
+    fn read_input(&self) -> Self::StorageValue {
+        (**self).read_input()
+    }
+
+    fn return_output(&mut self, value: &[u8]) {
+        (**self).return_output(value);
+    }
+
+    fn read_storage(&self, key: &[u8]) -> Option<Self::StorageValue> {
+        (**self).read_storage(key)
+    }
+
+    fn write_storage(&mut self, key: &[u8], value: &[u8]) -> Option<Self::StorageValue> {
+        (**self).write_storage(key, value)
+    }
+
+    fn remove_storage(&mut self, key: &[u8]) -> Option<Self::StorageValue> {
+        (**self).remove_storage(key)
+    }
+}
+
 impl IO for Runtime {
     type StorageValue = RegisterIndex;
 
@@ -69,6 +118,12 @@ impl IO for Runtime {
         Self::INPUT_REGISTER_ID
     }
 
+    fn return_output(&mut self, value: &[u8]) {
+        unsafe {
+            exports::value_return(value.len() as u64, value.as_ptr() as u64);
+        }
+    }
+
     fn read_storage(&self, key: &[u8]) -> Option<Self::StorageValue> {
         unsafe {
             if exports::storage_read(
@@ -100,4 +155,19 @@ impl IO for Runtime {
             }
         }
     }
+
+    fn remove_storage(&mut self, key: &[u8]) -> Option<Self::StorageValue> {
+        unsafe {
+            if exports::storage_remove(
+                key.len() as u64,
+                key.as_ptr() as u64,
+                Self::EVICT_REGISTER_ID.0,
+            ) == 1
+            {
+                Some(Self::EVICT_REGISTER_ID)
+            } else {
+                None
+            }
+        }
+    }
 }
@@ -0,0 +1,355 @@
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::string::String;
+use std::vec::Vec;
+
+use borsh::BorshSerialize;
+
+use crate::error::ContractError;
+use crate::runtime::{Env, PromiseHandler, PromiseId, StorageIntermediate, IO};
+use crate::types::{
+    AccountId, PromiseAction, PromiseBatchAction, PromiseCreateArgs, PromiseResult,
+};
+
+const DEFAULT_PREPAID_GAS: u64 = 300_000_000_000_000;
+
+std::thread_local! {
+    static LOGS: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Records the log of the current test.
+pub fn log(bytes: &[u8]) {
+    LOGS.with(|logs| {
+        logs.borrow_mut()
+            .push(String::from_utf8_lossy(bytes).into_owned());
+    });
+}
+
+/// Value read by the mock runtime.
+pub struct MockValue(Vec<u8>);
+
+impl StorageIntermediate for MockValue {
+    fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    fn copy_to_slice(&self, buffer: &mut [u8]) {
+        buffer.copy_from_slice(&self.0);
+    }
+
+    fn read_prefix(&self, buffer: &mut [u8]) -> Result<usize, ContractError> {
+        let len = self.0.len().min(buffer.len());
+        buffer[..len].copy_from_slice(&self.0[..len]);
+        Ok(len)
+    }
+}
+
+/// Function call created by the contract.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MockCall {
+    pub target_account_id: AccountId,
+    pub method: &'static str,
+    pub args: Vec<u8>,
+    pub attached_balance: u128,
+    pub attached_gas: u64,
+}
+
+impl From<&PromiseCreateArgs> for MockCall {
+    fn from(args: &PromiseCreateArgs) -> Self {
+        Self {
+            target_account_id: args.target_account_id,
+            method: args.method,
+            args: args.args.to_vec(),
+            attached_balance: args.attached_balance,
+            attached_gas: args.attached_gas,
+        }
+    }
+}
+
+/// Action of the batch created by the contract.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MockAction {
+    AddFullAccessKey,
+    DeleteAccount { beneficiary_id: AccountId },
+    DeployContract { code_len: usize },
+    FunctionCall { method: &'static str, gas: u64 },
+}
+
+/// Promise created by the contract. The id of the promise is its index in the list of the
+/// created promises.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MockPromise {
+    Call(MockCall),
+    Callback {
+        base: PromiseId,
+        call: MockCall,
+    },
+    And(Vec<PromiseId>),
+    Batch {
+        target_account_id: AccountId,
+        actions: Vec<MockAction>,
+    },
+}
+
+/// In-memory runtime for the tests of the contract methods. It records the storage, the output
+/// and the created promises, and returns the configured context and promise results.
+pub struct MockRuntime {
+    pub current_account_id: AccountId,
+    pub predecessor_account_id: AccountId,
+    pub attached_deposit: u128,
+    pub account_balance: u128,
+    pub storage_usage: u64,
+    pub block_height: u64,
+    pub prepaid_gas: u64,
+    pub used_gas: u64,
+    pub input: Vec<u8>,
+    pub storage: BTreeMap<Vec<u8>, Vec<u8>>,
+    pub promise_results: Vec<PromiseResult>,
+    pub promises: Vec<MockPromise>,
+    pub returned_promise: Option<PromiseId>,
+    pub output: Option<Vec<u8>>,
+}
+
+impl MockRuntime {
+    /// Creates the runtime of the contract with the given account, which is called by itself.
+    /// The logs of the previous runtime of the current test are cleared.
+    #[must_use]
+    pub fn new(current_account_id: &str) -> Self {
+        LOGS.with(|logs| logs.borrow_mut().clear());
+        let current_account_id = AccountId::new(current_account_id).unwrap();
+
+        Self {
+            current_account_id,
+            predecessor_account_id: current_account_id,
+            attached_deposit: 0,
+            account_balance: 0,
+            storage_usage: 0,
+            block_height: 0,
+            prepaid_gas: DEFAULT_PREPAID_GAS,
+            used_gas: 0,
+            input: Vec::new(),
+            storage: BTreeMap::new(),
+            promise_results: Vec::new(),
+            promises: Vec::new(),
+            returned_promise: None,
+            output: None,
+        }
+    }
+
+    #[must_use]
+    pub fn predecessor(mut self, account_id: &str) -> Self {
+        self.predecessor_account_id = AccountId::new(account_id).unwrap();
+        self
+    }
+
+    #[must_use]
+    pub const fn deposit(mut self, amount: u128) -> Self {
+        self.attached_deposit = amount;
+        self
+    }
+
+    #[must_use]
+    pub const fn balance(mut self, amount: u128) -> Self {
+        self.account_balance = amount;
+        self
+    }
+
+    #[must_use]
+    pub const fn block_height(mut self, block_height: u64) -> Self {
+        self.block_height = block_height;
+        self
+    }
+
+    #[must_use]
+    pub const fn gas(mut self, prepaid_gas: u64) -> Self {
+        self.prepaid_gas = prepaid_gas;
+        self
+    }
+
+    #[must_use]
+    pub fn input(mut self, input: &[u8]) -> Self {
+        self.input = input.to_vec();
+        self
+    }
+
+    #[must_use]
+    pub fn input_borsh<T: BorshSerialize>(self, value: &T) -> Self {
+        let input = crate::types::to_borsh(value).unwrap();
+        self.input(&input)
+    }
+
+    #[must_use]
+    pub fn promise_result(mut self, result: PromiseResult) -> Self {
+        self.promise_results.push(result);
+        self
+    }
+
+    /// Resets the call context, so the next method is called on the same storage.
+    #[must_use]
+    pub fn next_call(mut self) -> Self {
+        self.predecessor_account_id = self.current_account_id;
+        self.attached_deposit = 0;
+        self.input.clear();
+        self.promise_results.clear();
+        self.promises.clear();
+        self.returned_promise = None;
+        self.output = None;
+        LOGS.with(|logs| logs.borrow_mut().clear());
+        self
+    }
+
+    /// Function calls of the created promises, including the callbacks, in the creation order.
+    #[must_use]
+    pub fn calls(&self) -> Vec<&MockCall> {
+        self.promises
+            .iter()
+            .filter_map(|promise| match promise {
+                MockPromise::Call(call) | MockPromise::Callback { call, .. } => Some(call),
+                MockPromise::And(_) | MockPromise::Batch { .. } => None,
+            })
+            .collect()
+    }
+
+    /// Logs of the current test.
+    #[must_use]
+    pub fn logs() -> Vec<String> {
+        LOGS.with(|logs| logs.borrow().clone())
+    }
+
+    fn push(&mut self, promise: MockPromise) -> PromiseId {
+        self.promises.push(promise);
+        PromiseId::new(self.promises.len() as u64 - 1)
+    }
+}
+
+impl IO for MockRuntime {
+    type StorageValue = MockValue;
+
+    fn read_input(&self) -> Self::StorageValue {
+        MockValue(self.input.clone())
+    }
+
+    fn return_output(&mut self, value: &[u8]) {
+        self.output = Some(value.to_vec());
+    }
+
+    fn read_storage(&self, key: &[u8]) -> Option<Self::StorageValue> {
+        self.storage.get(key).cloned().map(MockValue)
+    }
+
+    fn write_storage(&mut self, key: &[u8], value: &[u8]) -> Option<Self::StorageValue> {
+        self.storage
+            .insert(key.to_vec(), value.to_vec())
+            .map(MockValue)
+    }
+
+    fn remove_storage(&mut self, key: &[u8]) -> Option<Self::StorageValue> {
+        self.storage.remove(key).map(MockValue)
+    }
+}
+
+impl Env for MockRuntime {
+    fn current_account_id(&self) -> AccountId {
+        self.current_account_id
+    }
+
+    fn predecessor_account_id(&self) -> AccountId {
+        self.predecessor_account_id
+    }
+
+    fn attached_deposit(&self) -> u128 {
+        self.attached_deposit
+    }
+
+    fn account_balance(&self) -> u128 {
+        self.account_balance
+    }
+
+    fn storage_usage(&self) -> u64 {
+        self.storage_usage
+    }
+
+    fn block_height(&self) -> u64 {
+        self.block_height
+    }
+
+    fn prepaid_gas(&self) -> u64 {
+        self.prepaid_gas
+    }
+
+    fn used_gas(&self) -> u64 {
+        self.used_gas
+    }
+}
+
+impl PromiseHandler for MockRuntime {
+    fn promise_results_count(&self) -> u64 {
+        self.promise_results.len() as u64
+    }
+
+    fn promise_result(&self, index: u64) -> Option<PromiseResult> {
+        usize::try_from(index)
+            .ok()
+            .and_then(|index| self.promise_results.get(index).cloned())
+    }
+
+    unsafe fn promise_create_call(&mut self, args: &PromiseCreateArgs) -> PromiseId {
+        self.push(MockPromise::Call(args.into()))
+    }
+
+    fn promise_and(&mut self, promises: &[PromiseId]) -> PromiseId {
+        self.push(MockPromise::And(promises.to_vec()))
+    }
+
+    unsafe fn promise_attach_callback(
+        &mut self,
+        base: PromiseId,
+        callback: &PromiseCreateArgs,
+    ) -> PromiseId {
+        self.push(MockPromise::Callback {
+            base,
+            call: callback.into(),
+        })
+    }
+
+    unsafe fn promise_create_batch<const S: usize>(
+        &mut self,
+        args: &PromiseBatchAction<'_, S>,
+    ) -> PromiseId {
+        let actions = args
+            .actions
+            .iter()
+            .map(|action| match action {
+                PromiseAction::AddFullAccessKey { .. } => MockAction::AddFullAccessKey,
+                PromiseAction::DeleteAccount { beneficiary_id } => MockAction::DeleteAccount {
+                    beneficiary_id: *beneficiary_id,
+                },
+                PromiseAction::DeployContract { code } => MockAction::DeployContract {
+                    code_len: code.len(),
+                },
+                PromiseAction::FunctionCall { method, gas, .. } => {
+                    MockAction::FunctionCall { method, gas: *gas }
+                }
+            })
+            .collect();
+
+        self.push(MockPromise::Batch {
+            target_account_id: args.target_account_id,
+            actions,
+        })
+    }
+
+    fn promise_return(&mut self, promise: PromiseId) {
+        // NEAR fails the call which returns the joint promise.
+        assert!(
+            !matches!(
+                usize::try_from(promise.raw())
+                    .ok()
+                    .and_then(|i| self.promises.get(i)),
+                Some(MockPromise::And(_))
+            ),
+            "CannotReturnJointPromise"
+        );
+        self.returned_promise = Some(promise);
+    }
+}
@@ -1,13 +1,18 @@
+use crate::error::ContractError;
 use crate::runtime::sys::exports;
 use crate::types::{AccountId, PromiseAction, PromiseBatchAction};
 
 pub use env::Env;
-pub use handler::PromiseHandler;
+pub use handler::{PromiseHandler, PromiseId, MAX_COMBINED_PROMISES};
 pub use io::{StorageIntermediate, IO};
+#[cfg(test)]
+pub use mock::{MockAction, MockPromise, MockRuntime};
 
 mod env;
 mod handler;
 mod io;
+#[cfg(test)]
+mod mock;
 mod sys;
 
 /// Wrapper type for indices in NEAR's register API.
@@ -30,6 +35,58 @@ impl StorageIntermediate for RegisterIndex {
     fn copy_to_slice(&self, buffer: &mut [u8]) {
         unsafe { exports::read_register(self.0, buffer.as_ptr() as u64) }
     }
+
+    fn read_prefix(&self, buffer: &mut [u8]) -> Result<usize, ContractError> {
+        let len = self.len();
+
+        if len <= buffer.len() {
+            self.copy_to_slice(&mut buffer[..len]);
+            return Ok(len);
+        }
+
+        // The register is read as a whole, so the larger value is read to the scratch buffer
+        // first.
+        let scratch = scratch_buffer(len)?;
+        self.copy_to_slice(scratch);
+        buffer.copy_from_slice(&scratch[..buffer.len()]);
+
+        Ok(buffer.len())
+    }
+}
+
+/// The largest register which is read by the contract: the code deployed by `upgrade` or the
+/// promise result which is read partially. It's the protocol's limit of the contract size, which
+/// is also the limit of the data returned by a call.
+pub const MAX_REGISTER_LEN: usize = 4 * 1024 * 1024;
+
+/// Returns the buffer for the register which doesn't fit the stack. The memory of the contract is
+/// grown by the pages of the buffer, so it's used only by the calls which read such a register.
+/// The contract has no allocator, so nothing else uses the grown memory.
+#[cfg(target_arch = "wasm32")]
+pub fn scratch_buffer(len: usize) -> Result<&'static mut [u8], ContractError> {
+    const PAGE_SIZE: usize = 64 * 1024;
+
+    if len > MAX_REGISTER_LEN {
+        return Err(ContractError::ResultTooLarge);
+    }
+
+    let first_page = core::arch::wasm32::memory_grow(0, len.div_ceil(PAGE_SIZE));
+
+    if first_page == usize::MAX {
+        return Err(ContractError::ResultTooLarge);
+    }
+
+    Ok(unsafe { core::slice::from_raw_parts_mut((first_page * PAGE_SIZE) as *mut u8, len) })
+}
+
+/// The host functions aren't available outside wasm, so the buffer is leaked from the heap.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn scratch_buffer(len: usize) -> Result<&'static mut [u8], ContractError> {
+    if len > MAX_REGISTER_LEN {
+        return Err(ContractError::ResultTooLarge);
+    }
+
+    Ok(std::vec![0; len].leak())
 }
 
 #[derive(Copy, Clone)]
@@ -39,20 +96,23 @@ impl Runtime {
     pub const READ_STORAGE_REGISTER_ID: RegisterIndex = RegisterIndex(0);
     pub const INPUT_REGISTER_ID: RegisterIndex = RegisterIndex(1);
     pub const WRITE_REGISTER_ID: RegisterIndex = RegisterIndex(2);
+    pub const EVICT_REGISTER_ID: RegisterIndex = RegisterIndex(3);
     pub const ENV_REGISTER_ID: RegisterIndex = RegisterIndex(4);
     pub const PROMISE_REGISTER_ID: RegisterIndex = RegisterIndex(5);
 
     /// Assumes a valid account ID has been written to `ENV_REGISTER_ID`
     /// by a previous call.
     pub(crate) fn read_account_id() -> AccountId {
-        let bytes = Self::ENV_REGISTER_ID.to_vec();
-        let str = core::str::from_utf8(bytes.as_ref()).expect("Invalid UTF-8 string");
+        let bytes = Self::ENV_REGISTER_ID.to_vec().sdk_unwrap();
+        let str = core::str::from_utf8(bytes.as_ref())
+            .map_err(|_| ContractError::BadUtf8String)
+            .sdk_unwrap();
         AccountId::new(str).unwrap_or_default()
     }
 
     pub(crate) unsafe fn append_batch_actions<const S: usize>(
         id: u64,
-        args: &PromiseBatchAction<S>,
+        args: &PromiseBatchAction<'_, S>,
     ) {
         for action in &args.actions {
             match action {
@@ -73,11 +133,36 @@ impl Runtime {
                         beneficiary_id_str.as_ptr() as _,
                     );
                 }
+                PromiseAction::DeployContract { code } => {
+                    exports::promise_batch_action_deploy_contract(
+                        id,
+                        code.len() as _,
+                        code.as_ptr() as _,
+                    );
+                }
+                PromiseAction::FunctionCall {
+                    method,
+                    args,
+                    attached_balance,
+                    gas,
+                } => {
+                    let method_name = method.as_bytes();
+                    exports::promise_batch_action_function_call(
+                        id,
+                        method_name.len() as _,
+                        method_name.as_ptr() as _,
+                        args.len() as _,
+                        args.as_ptr() as _,
+                        core::ptr::addr_of!(*attached_balance) as _,
+                        *gas,
+                    );
+                }
             }
         }
     }
 }
 
+#[cfg(not(test))]
 pub fn panic_utf8(bytes: &[u8]) -> ! {
     unsafe {
         exports::panic_utf8(bytes.len() as u64, bytes.as_ptr() as u64);
@@ -85,6 +170,25 @@ pub fn panic_utf8(bytes: &[u8]) -> ! {
     unreachable!()
 }
 
+/// The host functions aren't available in the tests, so the error is raised as a regular panic.
+#[cfg(test)]
+pub fn panic_utf8(bytes: &[u8]) -> ! {
+    panic!("{}", std::string::String::from_utf8_lossy(bytes))
+}
+
+#[cfg(not(test))]
+pub fn log_utf8(bytes: &[u8]) {
+    unsafe {
+        exports::log_utf8(bytes.len() as u64, bytes.as_ptr() as u64);
+    }
+}
+
+/// The host functions aren't available in the tests, so the logs are recorded by the mock.
+#[cfg(test)]
+pub fn log_utf8(bytes: &[u8]) {
+    mock::log(bytes);
+}
+
 pub trait SdkUnwrap<T> {
     fn sdk_unwrap(self) -> T;
 }
@@ -99,11 +203,11 @@ impl<T, E: AsRef<[u8]>> SdkUnwrap<T> for Result<T, E> {
 }
 
 pub trait SdkExpect<T> {
-    fn sdk_expect(self, msg: &str) -> T;
+    fn sdk_expect(self, error: ContractError) -> T;
 }
 
 impl<T> SdkExpect<T> for Option<T> {
-    fn sdk_expect(self, msg: &str) -> T {
-        self.unwrap_or_else(|| panic_utf8(msg.as_bytes()))
+    fn sdk_expect(self, error: ContractError) -> T {
+        self.unwrap_or_else(|| panic_utf8(error.as_ref()))
     }
 }
@@ -0,0 +1,472 @@
+use arrayvec::ArrayVec;
+use borsh::{BorshDeserialize, BorshSerialize};
+
+use crate::error::ContractError;
+use crate::math;
+use crate::runtime::{StorageIntermediate, IO};
+use crate::types::{AccountId, Address, BoundedVec, ChainId};
+
+/// The state is stored as the version byte followed by the borsh representation of the
+/// layout of that version.
+const STATE_STORAGE_KEY: &[u8] = b"FWD_VERSIONED_STATE";
+/// Key of the state written before the state became versioned.
+const LEGACY_STATE_STORAGE_KEY: &[u8] = b"FWD_STATE";
+
+/// Version of the unversioned state stored under the legacy key.
+const LEGACY_STATE_VERSION: u8 = 0;
+/// Version of the state layout written by the current code. It must be bumped on every change
+/// of the `State` layout together with adding a migration from the previous layout to
+/// `State::decode`.
+const STATE_VERSION: u8 = 1;
+
+/// Cost of storing a byte in yoctoNEAR.
+const STORAGE_PRICE_PER_BYTE: u128 = 10_000_000_000_000_000_000;
+/// Balance kept on top of the storage staking by default, which is 0.01 NEAR.
+pub const DEFAULT_RESERVE_MARGIN: u128 = 10_000_000_000_000_000_000_000;
+
+/// Denominator of the fee ratio in basis points.
+pub const BPS_DENOMINATOR: u128 = 10_000;
+/// Upper bound of the maximum fee ratio set at creation, which is the whole amount.
+pub const MAX_FEE_BPS: u16 = 10_000;
+/// Maximum fee ratio by default, which is 10% as the maximum fee of the fees contract.
+pub const DEFAULT_MAX_FEE_BPS: u16 = 1_000;
+
+/// Maximum number of addresses the tokens could be split between.
+pub const MAX_TARGETS: usize = 4;
+
+/// Current layout of the state.
+#[derive(BorshSerialize, BorshDeserialize)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(PartialEq, Debug))]
+pub struct State {
+    pub target: Target,
+    pub msg_format: MsgFormat,
+    /// Balance in yoctoNEAR kept on top of the storage staking when native NEAR is forwarded.
+    pub reserve_margin: u128,
+    /// Maximum ratio of the fee quoted by the fees contract to the amount in basis points.
+    pub max_fee_bps: u16,
+    /// Accounts which could start the forwarding with `forward`.
+    pub caller_policy: CallerPolicy,
+    pub wnear_contract_id: AccountId,
+    pub fees_contract_id: AccountId,
+    pub owner_id: AccountId,
+}
+
+impl State {
+    /// Writes the state with the current layout and removes the legacy one.
+    pub fn save<I: IO>(&self, io: &mut I) {
+        io.write_borsh(STATE_STORAGE_KEY, &(STATE_VERSION, self));
+        io.remove_storage(LEGACY_STATE_STORAGE_KEY);
+    }
+
+    /// Reads the state and upgrades it to the current layout. The upgraded state isn't written
+    /// back to the storage until `save` is called.
+    pub fn load<I: IO>(io: &I) -> Option<Self> {
+        if let Some(value) = io.read_storage(STATE_STORAGE_KEY) {
+            let data = value.to_vec().ok()?;
+            let (version, data) = data.split_first()?;
+            return Self::decode(*version, data);
+        }
+
+        let data = io.read_storage(LEGACY_STATE_STORAGE_KEY)?.to_vec().ok()?;
+        Self::decode(LEGACY_STATE_VERSION, &data)
+    }
+
+    pub fn is_owner(&self, account_id: AccountId) -> bool {
+        self.owner_id == account_id
+    }
+
+    /// Returns the balance which is kept when native NEAR is forwarded: the staking for the
+    /// used storage plus the margin.
+    pub fn native_reserve(&self, storage_usage: u64) -> u128 {
+        u128::from(storage_usage)
+            .saturating_mul(STORAGE_PRICE_PER_BYTE)
+            .saturating_add(self.reserve_margin)
+    }
+
+    /// Checks that the fee quoted by the fees contract doesn't exceed the maximum fee ratio. It
+    /// protects the forwarded tokens against a compromised or buggy fees contract.
+    ///
+    /// The fees contract charges at least one unit, so the fee of a single unit is accepted even
+    /// if the ratio rounds the maximum fee of a small amount down to zero.
+    pub fn check_fee(&self, amount: u128, fee: u128) -> Result<(), ContractError> {
+        let max_fee = math::mul_div(amount, u128::from(self.max_fee_bps), BPS_DENOMINATOR)
+            .ok_or(ContractError::FeeTooHigh)?
+            .max(1);
+
+        if fee > amount || fee > max_fee {
+            return Err(ContractError::FeeTooHigh);
+        }
+
+        Ok(())
+    }
+
+    /// Decodes the state written with the layout of the given version.
+    fn decode(version: u8, data: &[u8]) -> Option<Self> {
+        match version {
+            LEGACY_STATE_VERSION => LegacyState::try_from_slice(data).ok().map(Into::into),
+            STATE_VERSION => Self::try_from_slice(data).ok(),
+            _ => None,
+        }
+    }
+}
+
+/// Layout of the unversioned state with a single target address.
+#[derive(BorshSerialize, BorshDeserialize)]
+pub struct LegacyState {
+    pub target_address: Address,
+    pub target_network: AccountId,
+    pub wnear_contract_id: AccountId,
+    pub fees_contract_id: AccountId,
+    pub owner_id: AccountId,
+}
+
+impl From<LegacyState> for State {
+    fn from(state: LegacyState) -> Self {
+        Self {
+            target: Target::Evm {
+                network: state.target_network,
+                targets: Targets::single(state.target_address),
+            },
+            msg_format: MsgFormat::Address,
+            reserve_margin: DEFAULT_RESERVE_MARGIN,
+            max_fee_bps: DEFAULT_MAX_FEE_BPS,
+            caller_policy: CallerPolicy::Anyone,
+            wnear_contract_id: state.wnear_contract_id,
+            fees_contract_id: state.fees_contract_id,
+            owner_id: state.owner_id,
+        }
+    }
+}
+
+/// Accounts which could start the forwarding of the tokens. The owner could always start it.
+#[derive(Copy, Clone, PartialEq, Eq, BorshSerialize, BorshDeserialize)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug))]
+pub enum CallerPolicy {
+    /// Any account.
+    Anyone,
+    /// The owner only.
+    Owner,
+    /// The relayers added by the owner.
+    Relayers,
+}
+
+impl CallerPolicy {
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Anyone => "anyone",
+            Self::Owner => "owner",
+            Self::Relayers => "relayers",
+        }
+    }
+}
+
+/// Format of the `msg` of `ft_transfer_call` expected by the target network. It's not used for
+/// the NEAR account targets.
+#[derive(Copy, Clone, PartialEq, BorshSerialize, BorshDeserialize)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug))]
+pub enum MsgFormat {
+    /// Hex address without the `0x` prefix, which is expected by the Aurora engine.
+    Address,
+    /// Relayer which gets the fee from the bridged tokens followed by the fee and the address:
+    /// `<relayer_id>:<fee><address>`. The fee is encoded as a 32-byte little-endian hex number.
+    /// It's expected by the silos which pay the relayers.
+    RelayerFee { relayer_id: AccountId, fee: u128 },
+    /// Address prefixed with the chain: `<chain>:0x<address>`. It's expected by the bridge
+    /// lockers which send the tokens to other chains.
+    ChainPrefixed { chain: ChainId },
+    /// JSON transfer message of the Omni bridge with the chain-prefixed recipient and the fee
+    /// paid in the bridged tokens.
+    Omni { chain: ChainId, fee: u128 },
+}
+
+/// Receiver of the forwarded tokens.
+#[derive(Clone, PartialEq, BorshSerialize, BorshDeserialize)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug))]
+pub enum Target {
+    /// Addresses on the EVM network (Aurora or a silo) the tokens are bridged to with
+    /// `ft_transfer_call`.
+    Evm {
+        network: AccountId,
+        targets: Targets,
+    },
+    /// NEAR account the tokens are sent to with `ft_transfer`.
+    Near { account_id: AccountId },
+}
+
+impl Target {
+    /// Returns the target which receives the whole amount sent to the first address only.
+    /// The account id of the forwarder is derived from it.
+    pub fn primary(&self) -> Self {
+        match self {
+            Self::Evm { network, targets } => Self::Evm {
+                network: *network,
+                targets: Targets::single(targets.primary()),
+            },
+            Self::Near { account_id } => Self::Near {
+                account_id: *account_id,
+            },
+        }
+    }
+
+    /// Returns the target which receives the whole amount sent to the address with the given
+    /// index. The NEAR account target is the only receiver.
+    pub fn receiver(&self, idx: usize) -> Self {
+        match self {
+            Self::Evm { network, targets } => Self::Evm {
+                network: *network,
+                targets: Targets::single(targets.as_slice()[idx].address),
+            },
+            Self::Near { account_id } => Self::Near {
+                account_id: *account_id,
+            },
+        }
+    }
+
+    /// Returns `true` if the tokens are split between several receivers.
+    pub fn is_split(&self) -> bool {
+        self.count() > 1
+    }
+
+    /// Returns the number of the receivers, which is the maximum number of the transfers of
+    /// the forwarded tokens.
+    pub fn count(&self) -> usize {
+        match self {
+            Self::Evm { targets, .. } => targets.as_slice().len(),
+            Self::Near { .. } => 1,
+        }
+    }
+
+    /// Divides the amount between the receivers in the order of the targets.
+    pub fn split(&self, amount: u128) -> ArrayVec<u128, MAX_TARGETS> {
+        match self {
+            Self::Evm { targets, .. } => targets.split(amount),
+            Self::Near { .. } => core::iter::once(amount).collect(),
+        }
+    }
+}
+
+/// Address on the target network which receives a share of the forwarded tokens proportional
+/// to its weight.
+#[derive(Copy, Clone, PartialEq, Eq, BorshSerialize, BorshDeserialize)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug))]
+pub struct WeightedAddress {
+    pub address: Address,
+    pub weight: u32,
+}
+
+/// Non-empty list of the addresses with non-zero weights.
+#[derive(Clone, PartialEq, Eq, BorshSerialize, BorshDeserialize)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug))]
+pub struct Targets(BoundedVec<WeightedAddress, MAX_TARGETS>);
+
+impl Targets {
+    pub fn single(address: Address) -> Self {
+        let mut targets = BoundedVec::default();
+        targets.push(WeightedAddress { address, weight: 1 });
+        Self(targets)
+    }
+
+    pub fn new(targets: BoundedVec<WeightedAddress, MAX_TARGETS>) -> Result<Self, ContractError> {
+        if targets.is_empty() || targets.iter().any(|target| target.weight == 0) {
+            return Err(ContractError::InvalidTargets);
+        }
+
+        Ok(Self(targets))
+    }
+
+    /// Returns the first target, which receives the rounding dust of the split.
+    pub fn primary(&self) -> Address {
+        self.0[0].address
+    }
+
+    pub fn as_slice(&self) -> &[WeightedAddress] {
+        self.0.as_slice()
+    }
+
+    /// Divides the amount between the targets proportionally to their weights. The rounding
+    /// dust goes to the first target.
+    pub fn split(&self, amount: u128) -> ArrayVec<u128, MAX_TARGETS> {
+        let total_weight = self
+            .0
+            .iter()
+            .map(|target| u128::from(target.weight))
+            .sum::<u128>();
+        let mut amounts = self
+            .0
+            .iter()
+            .map(|target| {
+                math::mul_div(amount, u128::from(target.weight), total_weight).unwrap_or_default()
+            })
+            .collect::<ArrayVec<_, MAX_TARGETS>>();
+        let dust = amount - amounts.iter().sum::<u128>();
+        amounts[0] += dust;
+
+        amounts
+    }
+}
+
+#[cfg(test)]
+pub fn test_state(targets: Targets) -> State {
+    State {
+        target: Target::Evm {
+            network: AccountId::new("target.near").unwrap(),
+            targets,
+        },
+        msg_format: MsgFormat::Address,
+        reserve_margin: DEFAULT_RESERVE_MARGIN,
+        max_fee_bps: DEFAULT_MAX_FEE_BPS,
+        caller_policy: CallerPolicy::Anyone,
+        wnear_contract_id: AccountId::new("wnear.near").unwrap(),
+        fees_contract_id: AccountId::new("fees.near").unwrap(),
+        owner_id: AccountId::new("owner.near").unwrap(),
+    }
+}
+
+#[test]
+fn test_deserialize_state() {
+    let original = test_state(Targets::single(Address([1; 20])));
+
+    let bytes = crate::types::to_borsh(&original).unwrap();
+    let expected = State::try_from_slice(bytes.as_slice()).unwrap();
+
+    assert_eq!(original, expected);
+
+    let original = State {
+        target: Target::Near {
+            account_id: AccountId::new("exchange.near").unwrap(),
+        },
+        ..test_state(Targets::single(Address([1; 20])))
+    };
+
+    let bytes = crate::types::to_borsh(&original).unwrap();
+    let expected = State::try_from_slice(bytes.as_slice()).unwrap();
+
+    assert_eq!(original, expected);
+
+    let original = State {
+        msg_format: MsgFormat::Omni {
+            chain: ChainId::new("eth").unwrap(),
+            fee: 10,
+        },
+        ..test_state(Targets::single(Address([1; 20])))
+    };
+
+    let bytes = crate::types::to_borsh(&original).unwrap();
+    let expected = State::try_from_slice(bytes.as_slice()).unwrap();
+
+    assert_eq!(original, expected);
+}
+
+#[test]
+fn test_decode_versioned_state() {
+    let legacy = LegacyState {
+        target_address: Address([1; 20]),
+        target_network: AccountId::new("target.near").unwrap(),
+        wnear_contract_id: AccountId::new("wnear.near").unwrap(),
+        fees_contract_id: AccountId::new("fees.near").unwrap(),
+        owner_id: AccountId::new("owner.near").unwrap(),
+    };
+    let legacy = crate::types::to_borsh(&legacy).unwrap();
+    let original = test_state(Targets::single(Address([1; 20])));
+
+    assert_eq!(
+        State::decode(LEGACY_STATE_VERSION, &legacy).as_ref(),
+        Some(&original)
+    );
+
+    let versioned = crate::types::to_borsh(&(STATE_VERSION, &original)).unwrap();
+    let (version, data) = versioned.split_first().unwrap();
+    assert_eq!(*version, STATE_VERSION);
+    assert_eq!(State::decode(*version, data), Some(original));
+
+    assert_eq!(State::decode(STATE_VERSION + 1, data), None);
+}
+
+#[test]
+fn test_native_reserve() {
+    let state = test_state(Targets::single(Address([1; 20])));
+
+    assert_eq!(state.native_reserve(0), DEFAULT_RESERVE_MARGIN);
+    assert_eq!(
+        state.native_reserve(1_000),
+        1_000 * STORAGE_PRICE_PER_BYTE + DEFAULT_RESERVE_MARGIN
+    );
+    assert_eq!(
+        State {
+            reserve_margin: u128::MAX,
+            ..state
+        }
+        .native_reserve(1),
+        u128::MAX
+    );
+}
+
+#[test]
+fn test_split_between_targets() {
+    let targets = |weights: &[u32]| {
+        let targets = weights
+            .iter()
+            .enumerate()
+            .map(|(i, weight)| WeightedAddress {
+                address: Address([u8::try_from(i).unwrap(); 20]),
+                weight: *weight,
+            })
+            .collect::<ArrayVec<_, MAX_TARGETS>>();
+        Targets::new(targets.into())
+    };
+
+    assert_eq!(
+        targets(&[70, 30]).unwrap().split(1000).as_slice(),
+        [700, 300]
+    );
+    assert_eq!(
+        targets(&[1, 1, 1]).unwrap().split(100).as_slice(),
+        [34, 33, 33]
+    );
+    assert_eq!(targets(&[1, 2]).unwrap().split(1).as_slice(), [1, 0]);
+    assert_eq!(
+        targets(&[u32::MAX, u32::MAX])
+            .unwrap()
+            .split(u128::MAX)
+            .as_slice(),
+        [u128::MAX / 2 + 1, u128::MAX / 2]
+    );
+    assert_eq!(
+        Target::Near {
+            account_id: AccountId::new("exchange.near").unwrap()
+        }
+        .split(1000)
+        .as_slice(),
+        [1000]
+    );
+    assert_eq!(targets(&[]), Err(ContractError::InvalidTargets));
+    assert_eq!(targets(&[1, 0]), Err(ContractError::InvalidTargets));
+}
+
+#[test]
+fn test_check_fee() {
+    let state = test_state(Targets::single(Address([1; 20])));
+
+    assert_eq!(state.check_fee(1_000, 0), Ok(()));
+    assert_eq!(state.check_fee(1_000, 100), Ok(()));
+    assert_eq!(state.check_fee(1_000, 101), Err(ContractError::FeeTooHigh));
+    assert_eq!(state.check_fee(0, 1), Err(ContractError::FeeTooHigh));
+
+    // The minimum fee of the fees contract is accepted on the amounts too small for the ratio.
+    for amount in 1..10 {
+        assert_eq!(state.check_fee(amount, 1), Ok(()));
+        assert_eq!(state.check_fee(amount, 2), Err(ContractError::FeeTooHigh));
+    }
+
+    let state = State {
+        max_fee_bps: MAX_FEE_BPS,
+        ..test_state(Targets::single(Address([1; 20])))
+    };
+
+    assert_eq!(state.check_fee(1_000, 1_000), Ok(()));
+    assert_eq!(
+        state.check_fee(1_000, 1_001),
+        Err(ContractError::FeeTooHigh)
+    );
+    assert_eq!(state.check_fee(u128::MAX, u128::MAX), Ok(()));
+}
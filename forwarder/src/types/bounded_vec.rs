@@ -0,0 +1,79 @@
+use arrayvec::ArrayVec;
+use borsh::{io, BorshDeserialize, BorshSerialize};
+use core::ops::{Deref, DerefMut};
+
+/// Vector with a fixed capacity, which has the same borsh representation as `Vec`.
+#[derive(Clone, PartialEq, Eq)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug))]
+pub struct BoundedVec<T, const N: usize>(ArrayVec<T, N>);
+
+impl<T, const N: usize> Default for BoundedVec<T, N> {
+    fn default() -> Self {
+        Self(ArrayVec::new())
+    }
+}
+
+impl<T, const N: usize> From<ArrayVec<T, N>> for BoundedVec<T, N> {
+    fn from(value: ArrayVec<T, N>) -> Self {
+        Self(value)
+    }
+}
+
+impl<T, const N: usize> Deref for BoundedVec<T, N> {
+    type Target = ArrayVec<T, N>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<T, const N: usize> DerefMut for BoundedVec<T, N> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+impl<T: BorshSerialize, const N: usize> BorshSerialize for BoundedVec<T, N> {
+    fn serialize<W: io::Write>(&self, writer: &mut W) -> io::Result<()> {
+        self.0.as_slice().serialize(writer)
+    }
+}
+
+impl<T: BorshDeserialize, const N: usize> BorshDeserialize for BoundedVec<T, N> {
+    fn deserialize_reader<R: io::Read>(reader: &mut R) -> io::Result<Self> {
+        let len = u32::deserialize_reader(reader)? as usize;
+
+        if len > N {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Too many elements",
+            ));
+        }
+
+        let mut values = ArrayVec::new();
+
+        for _ in 0..len {
+            values.push(T::deserialize_reader(reader)?);
+        }
+
+        Ok(Self(values))
+    }
+}
+
+#[test]
+fn test_bounded_vec_borsh() {
+    let values: BoundedVec<u32, 2> = ArrayVec::from([1, 2]).into();
+    let bytes = crate::types::to_borsh(&values).unwrap();
+
+    assert_eq!(
+        bytes.as_slice(),
+        crate::types::to_borsh(&[1u32, 2].as_slice())
+            .unwrap()
+            .as_slice()
+    );
+    assert_eq!(
+        BoundedVec::<u32, 2>::try_from_slice(&bytes).unwrap(),
+        values
+    );
+    assert!(BoundedVec::<u32, 1>::try_from_slice(&bytes).is_err());
+}
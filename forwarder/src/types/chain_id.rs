@@ -0,0 +1,78 @@
+use arrayvec::ArrayString;
+use borsh::{io, BorshDeserialize, BorshSerialize};
+
+use crate::error::ContractError;
+
+const MAX_CHAIN_ID_LEN: usize = 16;
+
+/// Short name of a chain used as the prefix of the recipient by the bridges, e.g. `eth`.
+#[derive(Default, Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ChainId(ArrayString<MAX_CHAIN_ID_LEN>);
+
+impl ChainId {
+    pub fn new(chain_id: &str) -> Result<Self, ContractError> {
+        let is_valid = !chain_id.is_empty()
+            && chain_id
+                .bytes()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit());
+
+        if !is_valid {
+            return Err(ContractError::ParseChainIdError);
+        }
+
+        ArrayString::from(chain_id)
+            .map(Self)
+            .map_err(|_| ContractError::ParseChainIdError)
+    }
+
+    #[must_use]
+    pub fn as_str(&self) -> &str {
+        self.0.as_str()
+    }
+}
+
+impl BorshSerialize for ChainId {
+    fn serialize<W: io::Write>(&self, writer: &mut W) -> io::Result<()> {
+        <str as BorshSerialize>::serialize(self.as_str(), writer)
+    }
+}
+
+impl BorshDeserialize for ChainId {
+    fn deserialize_reader<R: io::Read>(reader: &mut R) -> io::Result<Self> {
+        let len = u32::deserialize_reader(reader)? as usize;
+
+        if len > MAX_CHAIN_ID_LEN {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Expected a string no more than 16 bytes long",
+            ));
+        }
+
+        let mut buf = [0u8; MAX_CHAIN_ID_LEN];
+        let buf = &mut buf[..len];
+        reader.read_exact(buf)?;
+
+        core::str::from_utf8(buf)
+            .ok()
+            .and_then(|s| Self::new(s).ok())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Invalid chain id"))
+    }
+}
+
+#[test]
+fn test_chain_id() {
+    let chain_id = ChainId::new("eth").unwrap();
+    assert_eq!(chain_id.as_str(), "eth");
+
+    let ser = crate::types::to_borsh(&chain_id).unwrap();
+    let expected = ChainId::try_from_slice(ser.as_slice()).unwrap();
+
+    assert_eq!(chain_id, expected);
+    assert_eq!(ChainId::new(""), Err(ContractError::ParseChainIdError));
+    assert_eq!(ChainId::new("Eth"), Err(ContractError::ParseChainIdError));
+    assert_eq!(ChainId::new("eth:"), Err(ContractError::ParseChainIdError));
+    assert_eq!(
+        ChainId::new("averyveryverylongchain"),
+        Err(ContractError::ParseChainIdError)
+    );
+}
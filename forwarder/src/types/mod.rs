@@ -4,29 +4,35 @@ use crate::error::ContractError;
 
 pub use account_id::AccountId;
 pub use address::Address;
+pub use bounded_vec::BoundedVec;
+pub use chain_id::ChainId;
 pub use promise::{
     PromiseAction, PromiseBatchAction, PromiseCreateArgs, PromiseResult, PromiseWithCallbackArgs,
 };
 
-pub type Vec<T> = arrayvec::ArrayVec<T, 256>;
+/// Buffer for the borsh values and the arguments of the promises. It fits the arguments of
+/// `ft_transfer_call` with the longest `msg` format.
+pub type Vec<T> = arrayvec::ArrayVec<T, 512>;
 
 mod account_id;
 mod address;
+mod bounded_vec;
+mod chain_id;
 mod promise;
 
 pub fn to_borsh<T>(value: &T) -> Result<Vec<u8>, ContractError>
 where
     T: BorshSerialize + ?Sized,
 {
-    let len = borsh::object_length(value).map_err(|_| ContractError::BorshSerializeError)?;
+    // The value is written to the whole buffer at once, which fails if it doesn't fit.
     let mut buf = Vec::new();
-    assert!(len <= buf.capacity());
-    unsafe {
-        buf.set_len(len);
-    }
+    buf.extend(core::iter::repeat_n(0, buf.capacity()));
+    let mut unused = buf.as_mut_slice();
     value
-        .serialize(&mut buf.as_mut_slice())
+        .serialize(&mut unused)
         .map_err(|_| ContractError::BorshSerializeError)?;
+    let len = unused.len();
+    buf.truncate(buf.capacity() - len);
 
     Ok(buf)
 }
@@ -1,3 +1,6 @@
+use borsh::BorshDeserialize;
+
+use crate::error::ContractError;
 use crate::types::{AccountId, Vec};
 
 pub struct PromiseCreateArgs {
@@ -13,19 +16,62 @@ pub struct PromiseWithCallbackArgs {
     pub callback: PromiseCreateArgs,
 }
 
-pub struct PromiseBatchAction<const S: usize> {
+pub struct PromiseBatchAction<'a, const S: usize> {
     pub target_account_id: AccountId,
-    pub actions: [PromiseAction; S],
+    pub actions: [PromiseAction<'a>; S],
 }
 
-pub enum PromiseAction {
-    AddFullAccessKey { public_key: [u8; 33], nonce: u64 },
-    DeleteAccount { beneficiary_id: AccountId },
+pub enum PromiseAction<'a> {
+    AddFullAccessKey {
+        public_key: [u8; 33],
+        nonce: u64,
+    },
+    DeleteAccount {
+        beneficiary_id: AccountId,
+    },
+    DeployContract {
+        code: &'a [u8],
+    },
+    FunctionCall {
+        method: &'static str,
+        args: &'a [u8],
+        attached_balance: u128,
+        gas: u64,
+    },
 }
 
 #[allow(clippy::large_enum_variant)]
+#[derive(Clone)]
 pub enum PromiseResult {
     Successful(Vec<u8>),
+    /// The successful result which doesn't fit `Vec`. Only its beginning is read.
+    TooLarge(Vec<u8>),
     Failed,
     NotReady,
 }
+
+impl PromiseResult {
+    /// Parses the whole successful result. Only the value at the beginning of the result which is
+    /// too large is parsed with `parse_prefix`, so the value could be read anyway.
+    ///
+    /// Fails with `ERR_RESULT_TOO_LARGE` if the beginning of the large result can't be parsed.
+    pub fn parse<T>(
+        &self,
+        parse: impl FnOnce(&[u8]) -> Result<T, ContractError>,
+        parse_prefix: impl FnOnce(&[u8]) -> Result<T, ContractError>,
+    ) -> Result<T, ContractError> {
+        match self {
+            Self::Successful(bytes) => parse(bytes),
+            Self::TooLarge(bytes) => parse_prefix(bytes).map_err(|_| ContractError::ResultTooLarge),
+            Self::Failed | Self::NotReady => Err(ContractError::PromiseFailed),
+        }
+    }
+
+    /// Parses the borsh-serialized value of the successful result.
+    pub fn parse_borsh<T: BorshDeserialize>(&self) -> Result<T, ContractError> {
+        self.parse(
+            |v| T::try_from_slice(v).map_err(|_| ContractError::BorshDeserializeError),
+            |mut v| T::deserialize(&mut v).map_err(|_| ContractError::BorshDeserializeError),
+        )
+    }
+}
@@ -5,6 +5,7 @@ use near_workspaces::{AccountId, Contract};
 pub trait Factory {
     async fn create(&self, params: &[DeployParameters]) -> anyhow::Result<Vec<AccountId>>;
     async fn forward(&self, forwarder_id: &AccountId, token_id: &AccountId) -> anyhow::Result<()>;
+    async fn upgrade(&self, forwarder_id: &AccountId) -> anyhow::Result<()>;
     async fn destroy(&self, forwarder_id: &AccountId) -> anyhow::Result<()>;
 }
 
@@ -40,6 +41,21 @@ impl Factory for Contract {
         Ok(())
     }
 
+    async fn upgrade(&self, forwarder_id: &AccountId) -> anyhow::Result<()> {
+        let result = self
+            .call("upgrade_forwarder")
+            .args_json(json!({
+                "forwarder_id": forwarder_id
+            }))
+            .max_gas()
+            .transact()
+            .await
+            .unwrap();
+        assert!(result.is_success());
+
+        Ok(())
+    }
+
     async fn destroy(&self, account_id: &AccountId) -> anyhow::Result<()> {
         let result = self
             .call("destroy_forwarder")
@@ -1,8 +1,35 @@
+use aurora_engine_types::types::Address;
+use aurora_forwarder_factory::MsgFormat;
+use near_sdk::serde_json::Value;
 use near_workspaces::types::NearToken;
 use near_workspaces::{AccountId, Contract};
 
 pub trait Forwarder {
     async fn forward(&self, token_id: &AccountId) -> anyhow::Result<()>;
+    async fn forward_amount(&self, token_id: &AccountId, amount: u128) -> anyhow::Result<()>;
+    async fn forward_many(&self, token_ids: &[&AccountId]) -> anyhow::Result<()>;
+    async fn set_target(&self, address: &str) -> anyhow::Result<()>;
+    async fn set_targets(&self, targets: &[(&str, u32)]) -> anyhow::Result<()>;
+    async fn set_target_network(&self, target_network: &AccountId) -> anyhow::Result<()>;
+    async fn set_target_account(&self, account_id: &AccountId) -> anyhow::Result<()>;
+    async fn set_msg_format(&self, msg_format: &MsgFormat) -> anyhow::Result<()>;
+    async fn unlock(&self, token_id: &AccountId) -> anyhow::Result<()>;
+    async fn set_transfer_gas(&self, token_id: &AccountId, gas: Option<u64>) -> anyhow::Result<()>;
+    async fn allow_token(&self, token_id: &AccountId) -> anyhow::Result<()>;
+    async fn deny_token(&self, token_id: &AccountId) -> anyhow::Result<()>;
+    async fn remove_token(&self, token_id: &AccountId) -> anyhow::Result<()>;
+    async fn migrate(&self) -> anyhow::Result<()>;
+    async fn get_state(&self) -> anyhow::Result<Value>;
+    async fn is_retargeted(&self) -> anyhow::Result<bool>;
+    async fn get_original_target(&self) -> anyhow::Result<Value>;
+    async fn get_target_address(&self) -> anyhow::Result<String>;
+    async fn get_targets(&self) -> anyhow::Result<Value>;
+    async fn get_target_account_id(&self) -> anyhow::Result<String>;
+    async fn get_msg_format(&self) -> anyhow::Result<Value>;
+    async fn is_locked(&self, token_id: &AccountId) -> anyhow::Result<bool>;
+    async fn is_token_allowed(&self, token_id: &AccountId) -> anyhow::Result<bool>;
+    async fn get_transfer_gas(&self, token_id: &AccountId) -> anyhow::Result<u64>;
+    async fn get_accrued_fee(&self, token_id: &AccountId) -> anyhow::Result<u128>;
 }
 
 impl Forwarder for Contract {
@@ -19,4 +46,239 @@ impl Forwarder for Contract {
 
         Ok(())
     }
+
+    async fn forward_amount(&self, token_id: &AccountId, amount: u128) -> anyhow::Result<()> {
+        let result = self
+            .call("forward")
+            .args_borsh((token_id, Some(amount)))
+            .deposit(NearToken::from_yoctonear(1))
+            .max_gas()
+            .transact()
+            .await
+            .unwrap();
+        assert!(result.is_success());
+
+        Ok(())
+    }
+
+    async fn forward_many(&self, token_ids: &[&AccountId]) -> anyhow::Result<()> {
+        let result = self
+            .call("forward_many")
+            .args_borsh(token_ids)
+            .deposit(NearToken::from_yoctonear(1))
+            .max_gas()
+            .transact()
+            .await
+            .unwrap();
+        assert!(result.is_success());
+
+        Ok(())
+    }
+
+    async fn set_target(&self, address: &str) -> anyhow::Result<()> {
+        let address = Address::decode(address.trim_start_matches("0x")).unwrap();
+        let result = self
+            .call("set_target")
+            .args_borsh(address)
+            .max_gas()
+            .transact()
+            .await
+            .unwrap();
+        assert!(result.is_success());
+
+        Ok(())
+    }
+
+    async fn set_targets(&self, targets: &[(&str, u32)]) -> anyhow::Result<()> {
+        let targets = targets
+            .iter()
+            .map(|(address, weight)| {
+                let address = Address::decode(address.trim_start_matches("0x")).unwrap();
+                (address, *weight)
+            })
+            .collect::<Vec<_>>();
+        let result = self
+            .call("set_targets")
+            .args_borsh(targets)
+            .max_gas()
+            .transact()
+            .await
+            .unwrap();
+        assert!(result.is_success());
+
+        Ok(())
+    }
+
+    async fn set_target_network(&self, target_network: &AccountId) -> anyhow::Result<()> {
+        let result = self
+            .call("set_target_network")
+            .args_borsh(target_network)
+            .max_gas()
+            .transact()
+            .await
+            .unwrap();
+        assert!(result.is_success());
+
+        Ok(())
+    }
+
+    async fn set_target_account(&self, account_id: &AccountId) -> anyhow::Result<()> {
+        let result = self
+            .call("set_target_account")
+            .args_borsh(account_id)
+            .max_gas()
+            .transact()
+            .await
+            .unwrap();
+        assert!(result.is_success());
+
+        Ok(())
+    }
+
+    async fn set_msg_format(&self, msg_format: &MsgFormat) -> anyhow::Result<()> {
+        let result = self
+            .call("set_msg_format")
+            .args_borsh(msg_format)
+            .max_gas()
+            .transact()
+            .await
+            .unwrap();
+        assert!(result.is_success());
+
+        Ok(())
+    }
+
+    async fn unlock(&self, token_id: &AccountId) -> anyhow::Result<()> {
+        let result = self
+            .call("unlock")
+            .args_borsh(token_id)
+            .max_gas()
+            .transact()
+            .await
+            .unwrap();
+        assert!(result.is_success());
+
+        Ok(())
+    }
+
+    async fn set_transfer_gas(&self, token_id: &AccountId, gas: Option<u64>) -> anyhow::Result<()> {
+        let result = self
+            .call("set_transfer_gas")
+            .args_borsh((token_id, gas))
+            .max_gas()
+            .transact()
+            .await?;
+        assert!(result.is_success());
+
+        Ok(())
+    }
+
+    async fn allow_token(&self, token_id: &AccountId) -> anyhow::Result<()> {
+        call_with_token(self, "allow_token", token_id).await
+    }
+
+    async fn deny_token(&self, token_id: &AccountId) -> anyhow::Result<()> {
+        call_with_token(self, "deny_token", token_id).await
+    }
+
+    async fn remove_token(&self, token_id: &AccountId) -> anyhow::Result<()> {
+        call_with_token(self, "remove_token", token_id).await
+    }
+
+    async fn migrate(&self) -> anyhow::Result<()> {
+        let result = self.call("migrate").max_gas().transact().await.unwrap();
+        assert!(result.is_success());
+
+        Ok(())
+    }
+
+    async fn get_state(&self) -> anyhow::Result<Value> {
+        self.view("get_state").await?.json().map_err(Into::into)
+    }
+
+    async fn get_target_address(&self) -> anyhow::Result<String> {
+        self.view("get_target_address")
+            .await?
+            .json()
+            .map_err(Into::into)
+    }
+
+    async fn get_targets(&self) -> anyhow::Result<Value> {
+        self.view("get_targets").await?.json().map_err(Into::into)
+    }
+
+    async fn get_target_account_id(&self) -> anyhow::Result<String> {
+        self.view("get_target_account_id")
+            .await?
+            .json()
+            .map_err(Into::into)
+    }
+
+    async fn is_locked(&self, token_id: &AccountId) -> anyhow::Result<bool> {
+        self.view("is_locked")
+            .args_borsh(token_id)
+            .await?
+            .json()
+            .map_err(Into::into)
+    }
+
+    async fn is_token_allowed(&self, token_id: &AccountId) -> anyhow::Result<bool> {
+        self.view("is_token_allowed")
+            .args_borsh(token_id)
+            .await?
+            .json()
+            .map_err(Into::into)
+    }
+
+    async fn get_transfer_gas(&self, token_id: &AccountId) -> anyhow::Result<u64> {
+        self.view("get_transfer_gas")
+            .args_borsh(token_id)
+            .await?
+            .json()
+            .map_err(Into::into)
+    }
+
+    async fn get_accrued_fee(&self, token_id: &AccountId) -> anyhow::Result<u128> {
+        let fee: String = self
+            .view("get_accrued_fee")
+            .args_borsh(token_id)
+            .await?
+            .json()?;
+
+        fee.parse().map_err(Into::into)
+    }
+
+    async fn get_msg_format(&self) -> anyhow::Result<Value> {
+        self.view("get_msg_format")
+            .await?
+            .json()
+            .map_err(Into::into)
+    }
+
+    async fn is_retargeted(&self) -> anyhow::Result<bool> {
+        self.view("is_retargeted").await?.json().map_err(Into::into)
+    }
+
+    async fn get_original_target(&self) -> anyhow::Result<Value> {
+        self.view("get_original_target")
+            .await?
+            .json()
+            .map_err(Into::into)
+    }
+}
+
+async fn call_with_token(
+    contract: &Contract,
+    method: &str,
+    token_id: &AccountId,
+) -> anyhow::Result<()> {
+    let result = contract
+        .call(method)
+        .args_borsh(token_id)
+        .max_gas()
+        .transact()
+        .await?;
+    assert!(result.is_success());
+
+    Ok(())
 }
@@ -9,6 +9,13 @@ pub trait FungibleToken {
     async fn ft_balance_of(&self, account_id: &AccountId) -> u128;
     async fn ft_transfer(&self, from: &Account, to: &AccountId, amount: u128)
         -> anyhow::Result<()>;
+    async fn ft_transfer_call(
+        &self,
+        from: &Account,
+        to: &AccountId,
+        amount: u128,
+        msg: &str,
+    ) -> anyhow::Result<()>;
     async fn storage_deposit(&self, account_id: &AccountId) -> anyhow::Result<()>;
 }
 
@@ -42,6 +49,24 @@ impl FungibleToken for Contract {
         Ok(())
     }
 
+    async fn ft_transfer_call(
+        &self,
+        from: &Account,
+        to: &AccountId,
+        amount: u128,
+        msg: &str,
+    ) -> anyhow::Result<()> {
+        let result = from
+            .call(self.id(), "ft_transfer_call")
+            .args_json(json!({ "receiver_id": to, "amount": amount.to_string(), "msg": msg }))
+            .deposit(NearToken::from_yoctonear(1))
+            .max_gas()
+            .transact()
+            .await?;
+        assert!(result.is_success());
+        Ok(())
+    }
+
     async fn storage_deposit(&self, account_id: &AccountId) -> anyhow::Result<()> {
         let result = self
             .call("storage_deposit")
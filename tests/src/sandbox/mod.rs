@@ -58,6 +58,18 @@ impl Sandbox {
             .as_yoctonear()
     }
 
+    /// Returns the balance which the forwarder keeps when native NEAR is forwarded.
+    pub async fn native_reserve(&self, forwarder_id: &AccountId) -> u128 {
+        self.worker
+            .view(forwarder_id, "get_native_reserve")
+            .await
+            .unwrap()
+            .json::<String>()
+            .unwrap()
+            .parse()
+            .unwrap()
+    }
+
     pub async fn deploy_ft(
         &self,
         total_supply: u128,
@@ -175,6 +187,80 @@ impl Sandbox {
         Ok(contract)
     }
 
+    /// Deploys the forwarder which sends the tokens to the NEAR account.
+    pub async fn deploy_near_forwarder(
+        &self,
+        target_account_id: &AccountId,
+        fees_account_id: &AccountId,
+        wnear_contract_id: &AccountId,
+    ) -> anyhow::Result<Contract> {
+        let fwd_account = self
+            .create_subaccount("near-forwarder", FORWARDER_MIN_BALANCE)
+            .await?;
+        let result = fwd_account.deploy(&code(FORWARDER_WASM_PATH)).await?;
+        assert!(result.is_success());
+        let contract = result.result;
+        // The target address and network are ignored if the NEAR account is passed.
+        let result = fwd_account
+            .call(contract.id(), "new")
+            .args_borsh((
+                Address::zero(),
+                target_account_id,
+                wnear_contract_id,
+                fees_account_id,
+                fwd_account.id(),
+                true,
+                Some(target_account_id),
+            ))
+            .max_gas()
+            .transact()
+            .await?;
+        assert!(result.is_success());
+
+        Ok(contract)
+    }
+
+    /// Deploys the forwarder with the state written in the layout which was used before the
+    /// state became versioned.
+    pub async fn deploy_legacy_forwarder(
+        &self,
+        target_network: &AccountId,
+        address: &str,
+        fees_account_id: &AccountId,
+        wnear_contract_id: &AccountId,
+    ) -> anyhow::Result<Contract> {
+        let name = forwarder_prefix(
+            address,
+            &target_network.as_str().parse().unwrap(),
+            &fees_account_id.as_str().parse().unwrap(),
+        );
+        let fwd_account = self.create_subaccount(&name, FORWARDER_MIN_BALANCE).await?;
+        let result = fwd_account.deploy(&code(FORWARDER_WASM_PATH)).await?;
+        assert!(result.is_success());
+        let contract = result.result;
+        let address = Address::decode(address.trim_start_matches("0x")).unwrap();
+        let mut state = address.as_bytes().to_vec();
+
+        for account_id in [
+            target_network,
+            wnear_contract_id,
+            fees_account_id,
+            fwd_account.id(),
+        ] {
+            let len = u32::try_from(account_id.len()).unwrap();
+            state.extend_from_slice(&len.to_le_bytes());
+            state.extend_from_slice(account_id.as_bytes());
+        }
+
+        self.worker
+            .patch(contract.id())
+            .state(b"FWD_STATE", &state)
+            .transact()
+            .await?;
+
+        Ok(contract)
+    }
+
     pub async fn deploy_fees(&self, supported_tokens: &[&AccountId]) -> anyhow::Result<Contract> {
         let fee_account = self.create_subaccount("fees", INIT_BALANCE_NEAR).await?;
         let result = fee_account.deploy(&code(FEES_WASM_PATH)).await?;
@@ -224,7 +310,23 @@ async fn add_function_key(contract: &Contract) -> anyhow::Result<SecretKey> {
     let pk = sk.public_key();
     let key = AccessKey::function_call_access(
         contract.id(),
-        &["create", "forward_tokens", "destroy_forwarder"],
+        &[
+            "create",
+            "forward_tokens",
+            "forward_many_tokens",
+            "set_forwarder_target",
+            "set_forwarder_targets",
+            "set_forwarder_target_network",
+            "set_forwarder_msg_format",
+            "set_forwarder_reserve_margin",
+            "unlock_forwarder_token",
+            "set_forwarder_transfer_gas",
+            "allow_forwarder_token",
+            "deny_forwarder_token",
+            "remove_forwarder_token",
+            "upgrade_forwarder",
+            "destroy_forwarder",
+        ],
         None,
     );
     let result = contract.batch().add_key(pk, key).transact().await?;
@@ -1,8 +1,9 @@
 use crate::sandbox::{aurora::Aurora, fungible_token::FungibleToken, Sandbox};
 use aurora_engine_types::types::Address;
+use aurora_forwarder::ContractError;
 use aurora_forwarder_factory::{DeployParameters, INIT_BALANCE, MAX_NUM_CONTRACTS};
-use near_workspaces::types::{AccessKeyPermission, NearToken, PublicKey};
-use near_workspaces::AccountId;
+use near_workspaces::types::{AccessKeyPermission, KeyType, NearToken, PublicKey, SecretKey};
+use near_workspaces::{AccountId, Contract};
 use std::str::FromStr;
 use std::sync::LazyLock;
 
@@ -41,34 +42,510 @@ async fn test_creating_ft() {
 
 #[tokio::test]
 async fn test_creating_forwarder() {
+    use crate::sandbox::forwarder::Forwarder;
+    use near_sdk::serde_json::json;
+
     let sandbox = Sandbox::new().await.unwrap();
     let aurora = sandbox.deploy_aurora("aurora").await.unwrap();
     let fees = sandbox.deploy_fees(&[]).await.unwrap();
-    let result = sandbox
+    let forwarder = sandbox
         .deploy_forwarder(
             aurora.id(),
             "0x17ffdf6becbbc34d5c7d3bf4a0ed4a680395d057",
             fees.id(),
             &WNEAR,
         )
-        .await;
-    assert!(result.is_ok());
+        .await
+        .unwrap();
+
+    assert_eq!(forwarder.get_target_address().await.unwrap(), RECEIVER);
+    assert_eq!(
+        forwarder.get_state().await.unwrap(),
+        json!({
+            "target_address": RECEIVER,
+            "target_network": aurora.id(),
+            "wnear_contract_id": *WNEAR,
+            "fees_contract_id": fees.id(),
+            "owner_id": forwarder.id(),
+        })
+    );
+}
+
+#[tokio::test]
+async fn test_retarget_forwarder() {
+    use crate::sandbox::forwarder::Forwarder;
+    use near_sdk::serde_json::json;
+
+    const NEW_RECEIVER: &str = "0x1111111111111111111111111111111111111111";
+
+    let sandbox = Sandbox::new().await.unwrap();
+    let (usdt, usdt_owner) = sandbox.deploy_ft(TOTAL_SUPPLY, "USDT", 6).await.unwrap();
+    let aurora = sandbox.deploy_aurora("aurora").await.unwrap();
+    let silo = sandbox.deploy_aurora("silo").await.unwrap();
+    usdt.storage_deposit(silo.id()).await.unwrap();
+    let erc20 = silo.deploy_erc20(usdt.id()).await.unwrap();
+    let fees = sandbox.deploy_fees(&[]).await.unwrap();
+    let forwarder = sandbox
+        .deploy_forwarder(aurora.id(), RECEIVER, fees.id(), &WNEAR)
+        .await
+        .unwrap();
+    usdt.storage_deposit(forwarder.id()).await.unwrap();
+
+    assert!(!forwarder.is_retargeted().await.unwrap());
+
+    forwarder.set_target(NEW_RECEIVER).await.unwrap();
+    forwarder.set_target_network(silo.id()).await.unwrap();
+
+    let original_target = json!({
+        "target_address": RECEIVER,
+        "target_network": aurora.id(),
+    });
+
+    assert!(forwarder.is_retargeted().await.unwrap());
+    assert_eq!(forwarder.get_target_address().await.unwrap(), NEW_RECEIVER);
+    assert_eq!(
+        forwarder.get_original_target().await.unwrap(),
+        original_target
+    );
+
+    usdt.ft_transfer(&usdt_owner, forwarder.id(), 1_000_000)
+        .await
+        .unwrap();
+    forwarder.forward(usdt.id()).await.unwrap();
+
+    assert_eq!(erc20.balance_of(NEW_RECEIVER).await, 1_000_000);
+
+    forwarder.set_target(RECEIVER).await.unwrap();
+    forwarder.set_target_network(aurora.id()).await.unwrap();
+
+    assert!(!forwarder.is_retargeted().await.unwrap());
+    assert_eq!(
+        forwarder.get_original_target().await.unwrap(),
+        original_target
+    );
+}
+
+#[tokio::test]
+async fn test_set_msg_format() {
+    use crate::sandbox::forwarder::Forwarder;
+    use aurora_forwarder_factory::MsgFormat;
+    use near_sdk::json_types::U128;
+    use near_sdk::serde_json::json;
+
+    let sandbox = Sandbox::new().await.unwrap();
+    let fees = sandbox.deploy_fees(&[]).await.unwrap();
+    let forwarder = sandbox
+        .deploy_forwarder(
+            &"omni.test.near".parse().unwrap(),
+            RECEIVER,
+            fees.id(),
+            &WNEAR,
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(
+        forwarder.get_msg_format().await.unwrap(),
+        json!({"format": "address"})
+    );
+
+    forwarder
+        .set_msg_format(&MsgFormat::Omni {
+            chain: "eth".to_string(),
+            fee: U128(100),
+        })
+        .await
+        .unwrap();
+
+    assert_eq!(
+        forwarder.get_msg_format().await.unwrap(),
+        json!({"format": "omni", "chain": "eth", "fee": "100"})
+    );
+}
+
+#[tokio::test]
+async fn test_migrate_legacy_state() {
+    use crate::sandbox::forwarder::Forwarder;
+    use near_sdk::serde_json::json;
+
+    let sandbox = Sandbox::new().await.unwrap();
+    let aurora = sandbox.deploy_aurora("aurora").await.unwrap();
+    let fees = sandbox.deploy_fees(&[]).await.unwrap();
+    let forwarder = sandbox
+        .deploy_legacy_forwarder(aurora.id(), RECEIVER, fees.id(), &WNEAR)
+        .await
+        .unwrap();
+    let expected_state = json!({
+        "target_address": RECEIVER,
+        "target_network": aurora.id(),
+        "wnear_contract_id": *WNEAR,
+        "fees_contract_id": fees.id(),
+        "owner_id": forwarder.id(),
+    });
+
+    // The legacy state is readable before the migration.
+    assert_eq!(forwarder.get_state().await.unwrap(), expected_state);
+
+    forwarder.migrate().await.unwrap();
+
+    let storage = forwarder.view_state().await.unwrap();
+    assert!(!storage.contains_key(b"FWD_STATE".as_slice()));
+    assert_eq!(
+        storage.get(b"FWD_VERSIONED_STATE".as_slice()).unwrap()[0],
+        6
+    );
+    assert_eq!(forwarder.get_state().await.unwrap(), expected_state);
+}
+
+#[tokio::test]
+async fn test_creating_erc20() {
+    let sandbox = Sandbox::new().await.unwrap();
+    let (ft, _) = sandbox.deploy_ft(TOTAL_SUPPLY, "USDC", 6).await.unwrap();
+    let aurora = sandbox.deploy_aurora("aurora").await.unwrap();
+    let erc20 = aurora.deploy_erc20(ft.id()).await.unwrap();
+    assert_eq!(
+        erc20.address(),
+        "0x35c61bd8f7cb50410abded58646dbdd6c447d135"
+    );
+}
+
+#[tokio::test]
+async fn test_main_successful_flow() {
+    use crate::sandbox::forwarder::Forwarder;
+
+    let forward_amount = 1_000_000_000;
+    let fee_percent = 5;
+    let sandbox = Sandbox::new().await.unwrap();
+    let (ft, ft_owner) = sandbox.deploy_ft(TOTAL_SUPPLY, "USDT", 6).await.unwrap();
+
+    let aurora = sandbox.deploy_aurora("aurora").await.unwrap();
+    ft.storage_deposit(aurora.id()).await.unwrap();
+
+    let erc20 = aurora.deploy_erc20(ft.id()).await.unwrap();
+    assert_eq!(erc20.balance_of(RECEIVER).await, 0);
+
+    let fees = sandbox.deploy_fees(&[ft.id()]).await.unwrap();
+    ft.storage_deposit(fees.id()).await.unwrap();
+
+    let forwarder = sandbox
+        .deploy_forwarder(aurora.id(), RECEIVER, fees.id(), &WNEAR)
+        .await
+        .unwrap();
+    ft.storage_deposit(forwarder.id()).await.unwrap();
+
+    ft.ft_transfer(&ft_owner, forwarder.id(), forward_amount)
+        .await
+        .unwrap();
+
+    assert_eq!(ft.ft_balance_of(forwarder.id()).await, forward_amount);
+    assert_eq!(ft.ft_balance_of(aurora.id()).await, 0);
+
+    forwarder.forward(ft.id()).await.unwrap();
+
+    let fee = (forward_amount * fee_percent) / 100;
+    let balance = forward_amount - fee;
+
+    assert_eq!(erc20.balance_of(RECEIVER).await, balance);
+    assert_eq!(ft.ft_balance_of(aurora.id()).await, balance);
+    assert_eq!(ft.ft_balance_of(fees.id()).await, fee);
+    assert_eq!(ft.ft_balance_of(forwarder.id()).await, 0);
+    assert_eq!(
+        ft.ft_balance_of(ft_owner.id()).await,
+        TOTAL_SUPPLY - forward_amount
+    );
+}
+
+#[tokio::test]
+async fn test_forward_part_of_balance() {
+    use crate::sandbox::forwarder::Forwarder;
+
+    let balance = 1_000_000_000;
+    let forward_amount = 400_000_000;
+    let fee_percent = 5;
+    let sandbox = Sandbox::new().await.unwrap();
+    let (ft, ft_owner) = sandbox.deploy_ft(TOTAL_SUPPLY, "USDT", 6).await.unwrap();
+
+    let aurora = sandbox.deploy_aurora("aurora").await.unwrap();
+    ft.storage_deposit(aurora.id()).await.unwrap();
+
+    let erc20 = aurora.deploy_erc20(ft.id()).await.unwrap();
+
+    let fees = sandbox.deploy_fees(&[ft.id()]).await.unwrap();
+    ft.storage_deposit(fees.id()).await.unwrap();
+
+    let forwarder = sandbox
+        .deploy_forwarder(aurora.id(), RECEIVER, fees.id(), &WNEAR)
+        .await
+        .unwrap();
+    ft.storage_deposit(forwarder.id()).await.unwrap();
+
+    ft.ft_transfer(&ft_owner, forwarder.id(), balance)
+        .await
+        .unwrap();
+
+    forwarder
+        .forward_amount(ft.id(), forward_amount)
+        .await
+        .unwrap();
+
+    let fee = (forward_amount * fee_percent) / 100;
+
+    assert_eq!(erc20.balance_of(RECEIVER).await, forward_amount - fee);
+    assert_eq!(ft.ft_balance_of(fees.id()).await, fee);
+    assert_eq!(
+        ft.ft_balance_of(forwarder.id()).await,
+        balance - forward_amount
+    );
+
+    // An amount greater than the balance is rejected. The callback reports the failure
+    // without panicking, so the lock of the token is released.
+    let result = forwarder
+        .call("forward")
+        .args_borsh((ft.id(), Some(balance)))
+        .deposit(NearToken::from_yoctonear(1))
+        .max_gas()
+        .transact()
+        .await
+        .unwrap();
+    assert!(result
+        .logs()
+        .iter()
+        .any(|log| ContractError::from_message(log) == Some(ContractError::AmountExceedsBalance)));
+    assert!(!forwarder.is_locked(ft.id()).await.unwrap());
+    assert_eq!(
+        ft.ft_balance_of(forwarder.id()).await,
+        balance - forward_amount
+    );
+}
+
+#[tokio::test]
+async fn test_forward_is_locked_while_in_progress() {
+    use crate::sandbox::forwarder::Forwarder;
+
+    let sandbox = Sandbox::new().await.unwrap();
+    let (ft, ft_owner) = sandbox.deploy_ft(TOTAL_SUPPLY, "USDT", 6).await.unwrap();
+    let aurora = sandbox.deploy_aurora("aurora").await.unwrap();
+    ft.storage_deposit(aurora.id()).await.unwrap();
+    let erc20 = aurora.deploy_erc20(ft.id()).await.unwrap();
+    let fees = sandbox.deploy_fees(&[]).await.unwrap();
+    let forwarder = sandbox
+        .deploy_forwarder(aurora.id(), RECEIVER, fees.id(), &WNEAR)
+        .await
+        .unwrap();
+    ft.storage_deposit(forwarder.id()).await.unwrap();
+    ft.ft_transfer(&ft_owner, forwarder.id(), 1_000_000)
+        .await
+        .unwrap();
+
+    // Simulate the forwarding which didn't finish.
+    let lock_key = [b"FWD_LOCK".as_slice(), ft.id().as_bytes()].concat();
+    sandbox
+        .worker
+        .patch(forwarder.id())
+        .state(&lock_key, &[1])
+        .transact()
+        .await
+        .unwrap();
+
+    assert!(forwarder.is_locked(ft.id()).await.unwrap());
+
+    let result = forwarder
+        .call("forward")
+        .args_borsh(ft.id())
+        .deposit(NearToken::from_yoctonear(1))
+        .max_gas()
+        .transact()
+        .await
+        .unwrap();
+    assert!(result.is_failure());
+    assert_eq!(erc20.balance_of(RECEIVER).await, 0);
+
+    forwarder.unlock(ft.id()).await.unwrap();
+    forwarder.forward(ft.id()).await.unwrap();
+
+    assert!(!forwarder.is_locked(ft.id()).await.unwrap());
+    assert_eq!(erc20.balance_of(RECEIVER).await, 1_000_000);
+}
+
+#[tokio::test]
+async fn test_forward_allowed_tokens_only() {
+    use crate::sandbox::forwarder::Forwarder;
+
+    let sandbox = Sandbox::new().await.unwrap();
+    let (ft, ft_owner) = sandbox.deploy_ft(TOTAL_SUPPLY, "USDT", 6).await.unwrap();
+    let aurora = sandbox.deploy_aurora("aurora").await.unwrap();
+    ft.storage_deposit(aurora.id()).await.unwrap();
+    let erc20 = aurora.deploy_erc20(ft.id()).await.unwrap();
+    let fees = sandbox.deploy_fees(&[]).await.unwrap();
+    let forwarder = sandbox
+        .deploy_forwarder(aurora.id(), RECEIVER, fees.id(), &WNEAR)
+        .await
+        .unwrap();
+    ft.storage_deposit(forwarder.id()).await.unwrap();
+    ft.ft_transfer(&ft_owner, forwarder.id(), 1_000_000)
+        .await
+        .unwrap();
+
+    assert!(forwarder.is_token_allowed(ft.id()).await.unwrap());
+
+    // The denied token isn't forwarded.
+    forwarder.deny_token(ft.id()).await.unwrap();
+    assert!(!forwarder.is_token_allowed(ft.id()).await.unwrap());
+    let result = forwarder
+        .call("forward")
+        .args_borsh(ft.id())
+        .deposit(NearToken::from_yoctonear(1))
+        .max_gas()
+        .transact()
+        .await
+        .unwrap();
+    assert!(result.is_failure());
+
+    // Once any token is allowed, the rest of the tokens aren't forwarded.
+    forwarder.remove_token(ft.id()).await.unwrap();
+    forwarder.allow_token(&WNEAR).await.unwrap();
+    assert!(!forwarder.is_token_allowed(ft.id()).await.unwrap());
+    let result = forwarder
+        .call("forward")
+        .args_borsh(ft.id())
+        .deposit(NearToken::from_yoctonear(1))
+        .max_gas()
+        .transact()
+        .await
+        .unwrap();
+    assert!(result.is_failure());
+    assert_eq!(erc20.balance_of(RECEIVER).await, 0);
+
+    forwarder.allow_token(ft.id()).await.unwrap();
+    forwarder.forward(ft.id()).await.unwrap();
+    assert_eq!(erc20.balance_of(RECEIVER).await, 1_000_000);
 }
 
-#[tokio::test]
-async fn test_creating_erc20() {
-    let sandbox = Sandbox::new().await.unwrap();
-    let (ft, _) = sandbox.deploy_ft(TOTAL_SUPPLY, "USDC", 6).await.unwrap();
-    let aurora = sandbox.deploy_aurora("aurora").await.unwrap();
-    let erc20 = aurora.deploy_erc20(ft.id()).await.unwrap();
-    assert_eq!(
-        erc20.address(),
-        "0x35c61bd8f7cb50410abded58646dbdd6c447d135"
-    );
+#[tokio::test]
+async fn test_forward_with_transfer_gas_override() {
+    use crate::sandbox::forwarder::Forwarder;
+
+    const DEFAULT_TRANSFER_GAS: u64 = 50_000_000_000_000;
+
+    let sandbox = Sandbox::new().await.unwrap();
+    let (ft, ft_owner) = sandbox.deploy_ft(TOTAL_SUPPLY, "USDT", 6).await.unwrap();
+    let aurora = sandbox.deploy_aurora("aurora").await.unwrap();
+    ft.storage_deposit(aurora.id()).await.unwrap();
+    let erc20 = aurora.deploy_erc20(ft.id()).await.unwrap();
+    let fees = sandbox.deploy_fees(&[]).await.unwrap();
+    let forwarder = sandbox
+        .deploy_forwarder(aurora.id(), RECEIVER, fees.id(), &WNEAR)
+        .await
+        .unwrap();
+    ft.storage_deposit(forwarder.id()).await.unwrap();
+    ft.ft_transfer(&ft_owner, forwarder.id(), 1_000_000)
+        .await
+        .unwrap();
+
+    assert_eq!(
+        forwarder.get_transfer_gas(ft.id()).await.unwrap(),
+        DEFAULT_TRANSFER_GAS
+    );
+
+    // The attached gas can't cover the transfers, so the forwarding fails before any promise.
+    forwarder
+        .set_transfer_gas(ft.id(), Some(290_000_000_000_000))
+        .await
+        .unwrap();
+    let result = forwarder
+        .call("forward")
+        .args_borsh(ft.id())
+        .deposit(NearToken::from_yoctonear(1))
+        .max_gas()
+        .transact()
+        .await
+        .unwrap();
+    assert_eq!(
+        ContractError::from_message(&result.into_result().unwrap_err().to_string()),
+        Some(ContractError::NotEnoughGas)
+    );
+    assert!(!forwarder.is_locked(ft.id()).await.unwrap());
+
+    forwarder.set_transfer_gas(ft.id(), None).await.unwrap();
+    assert_eq!(
+        forwarder.get_transfer_gas(ft.id()).await.unwrap(),
+        DEFAULT_TRANSFER_GAS
+    );
+
+    forwarder.forward(ft.id()).await.unwrap();
+    assert_eq!(erc20.balance_of(RECEIVER).await, 1_000_000);
+}
+
+#[tokio::test]
+async fn test_forward_on_ft_transfer_call() {
+    let forward_amount = 1_000_000_000;
+    let fee_percent = 5;
+    let sandbox = Sandbox::new().await.unwrap();
+    let (ft, ft_owner) = sandbox.deploy_ft(TOTAL_SUPPLY, "USDT", 6).await.unwrap();
+
+    let aurora = sandbox.deploy_aurora("aurora").await.unwrap();
+    ft.storage_deposit(aurora.id()).await.unwrap();
+
+    let erc20 = aurora.deploy_erc20(ft.id()).await.unwrap();
+
+    let fees = sandbox.deploy_fees(&[ft.id()]).await.unwrap();
+    ft.storage_deposit(fees.id()).await.unwrap();
+
+    let forwarder = sandbox
+        .deploy_forwarder(aurora.id(), RECEIVER, fees.id(), &WNEAR)
+        .await
+        .unwrap();
+    ft.storage_deposit(forwarder.id()).await.unwrap();
+
+    ft.ft_transfer_call(&ft_owner, forwarder.id(), forward_amount, "")
+        .await
+        .unwrap();
+
+    let fee = (forward_amount * fee_percent) / 100;
+    let balance = forward_amount - fee;
+
+    assert_eq!(erc20.balance_of(RECEIVER).await, balance);
+    assert_eq!(ft.ft_balance_of(aurora.id()).await, balance);
+    assert_eq!(ft.ft_balance_of(fees.id()).await, fee);
+    assert_eq!(ft.ft_balance_of(forwarder.id()).await, 0);
+    assert_eq!(
+        ft.ft_balance_of(ft_owner.id()).await,
+        TOTAL_SUPPLY - forward_amount
+    );
+}
+
+#[tokio::test]
+async fn test_fee_is_not_charged_for_refunded_tokens() {
+    use crate::sandbox::forwarder::Forwarder;
+
+    let forward_amount = 1_000_000_000;
+    let sandbox = Sandbox::new().await.unwrap();
+    let (ft, ft_owner) = sandbox.deploy_ft(TOTAL_SUPPLY, "USDT", 6).await.unwrap();
+
+    let fees = sandbox.deploy_fees(&[ft.id()]).await.unwrap();
+    ft.storage_deposit(fees.id()).await.unwrap();
+
+    // The target network isn't registered in the token contract, so `ft_transfer_call` fails
+    // and the tokens stay on the forwarder.
+    let silo_account_id = "silo.test.near".parse().unwrap();
+    let forwarder = sandbox
+        .deploy_forwarder(&silo_account_id, RECEIVER, fees.id(), &WNEAR)
+        .await
+        .unwrap();
+    ft.storage_deposit(forwarder.id()).await.unwrap();
+
+    ft.ft_transfer(&ft_owner, forwarder.id(), forward_amount)
+        .await
+        .unwrap();
+
+    forwarder.forward(ft.id()).await.unwrap();
+
+    assert_eq!(ft.ft_balance_of(forwarder.id()).await, forward_amount);
+    assert_eq!(ft.ft_balance_of(fees.id()).await, 0);
 }
 
 #[tokio::test]
-async fn test_main_successful_flow() {
+async fn test_forward_to_near_account() {
     use crate::sandbox::forwarder::Forwarder;
 
     let forward_amount = 1_000_000_000;
@@ -76,41 +553,47 @@ async fn test_main_successful_flow() {
     let sandbox = Sandbox::new().await.unwrap();
     let (ft, ft_owner) = sandbox.deploy_ft(TOTAL_SUPPLY, "USDT", 6).await.unwrap();
 
-    let aurora = sandbox.deploy_aurora("aurora").await.unwrap();
-    ft.storage_deposit(aurora.id()).await.unwrap();
-
-    let erc20 = aurora.deploy_erc20(ft.id()).await.unwrap();
-    assert_eq!(erc20.balance_of(RECEIVER).await, 0);
-
     let fees = sandbox.deploy_fees(&[ft.id()]).await.unwrap();
     ft.storage_deposit(fees.id()).await.unwrap();
 
+    let exchange = sandbox
+        .create_subaccount("exchange", NearToken::from_near(1))
+        .await
+        .unwrap();
+    ft.storage_deposit(exchange.id()).await.unwrap();
+
     let forwarder = sandbox
-        .deploy_forwarder(aurora.id(), RECEIVER, fees.id(), &WNEAR)
+        .deploy_near_forwarder(exchange.id(), fees.id(), &WNEAR)
         .await
         .unwrap();
     ft.storage_deposit(forwarder.id()).await.unwrap();
 
+    assert_eq!(
+        forwarder.get_target_account_id().await.unwrap(),
+        exchange.id().as_str()
+    );
+
     ft.ft_transfer(&ft_owner, forwarder.id(), forward_amount)
         .await
         .unwrap();
-
-    assert_eq!(ft.ft_balance_of(forwarder.id()).await, forward_amount);
-    assert_eq!(ft.ft_balance_of(aurora.id()).await, 0);
-
     forwarder.forward(ft.id()).await.unwrap();
 
-    let fee = (forward_amount * fee_percent) / 100;
-    let balance = forward_amount - fee;
-
-    assert_eq!(erc20.balance_of(RECEIVER).await, balance);
-    assert_eq!(ft.ft_balance_of(aurora.id()).await, balance);
+    let fee = forward_amount * fee_percent / 100;
+    assert_eq!(ft.ft_balance_of(exchange.id()).await, forward_amount - fee);
     assert_eq!(ft.ft_balance_of(fees.id()).await, fee);
     assert_eq!(ft.ft_balance_of(forwarder.id()).await, 0);
+
+    let other = sandbox
+        .create_subaccount("other-exchange", NearToken::from_near(1))
+        .await
+        .unwrap();
+    forwarder.set_target_account(other.id()).await.unwrap();
+
     assert_eq!(
-        ft.ft_balance_of(ft_owner.id()).await,
-        TOTAL_SUPPLY - forward_amount
+        forwarder.get_target_account_id().await.unwrap(),
+        other.id().as_str()
     );
+    assert!(forwarder.is_retargeted().await.unwrap());
 }
 
 #[allow(clippy::similar_names)]
@@ -143,6 +626,9 @@ async fn test_forward_two_tokens() {
             target_address: RECEIVER.to_string(),
             target_network: aurora.id().as_str().parse().unwrap(),
             wnear_contract_id: WNEAR.clone(),
+            skip_full_access_key: false,
+            max_fee_bps: None,
+            target_account_id: None,
         }])
         .await
         .unwrap();
@@ -189,6 +675,270 @@ async fn test_forward_two_tokens() {
     );
 }
 
+#[tokio::test]
+async fn test_accrue_fee_if_fees_contract_is_not_registered() {
+    use crate::sandbox::forwarder::Forwarder;
+
+    let forward_amount = 1_000_000_000;
+    let fee = forward_amount * 5 / 100;
+    let sandbox = Sandbox::new().await.unwrap();
+    let (ft, ft_owner) = sandbox.deploy_ft(TOTAL_SUPPLY, "USDT", 6).await.unwrap();
+    let aurora = sandbox.deploy_aurora("aurora").await.unwrap();
+    ft.storage_deposit(aurora.id()).await.unwrap();
+    let erc20 = aurora.deploy_erc20(ft.id()).await.unwrap();
+    // The fees contract isn't registered on the token, so the fee can't be transferred to it.
+    let fees = sandbox.deploy_fees(&[ft.id()]).await.unwrap();
+    let forwarder = sandbox
+        .deploy_forwarder(aurora.id(), RECEIVER, fees.id(), &WNEAR)
+        .await
+        .unwrap();
+    ft.storage_deposit(forwarder.id()).await.unwrap();
+
+    ft.ft_transfer(&ft_owner, forwarder.id(), forward_amount)
+        .await
+        .unwrap();
+    forwarder.forward(ft.id()).await.unwrap();
+
+    assert_eq!(erc20.balance_of(RECEIVER).await, forward_amount - fee);
+    assert_eq!(ft.ft_balance_of(forwarder.id()).await, fee);
+    assert_eq!(forwarder.get_accrued_fee(ft.id()).await.unwrap(), fee);
+
+    // The accrued fee isn't forwarded with the next deposit.
+    ft.ft_transfer(&ft_owner, forwarder.id(), forward_amount)
+        .await
+        .unwrap();
+    forwarder.forward(ft.id()).await.unwrap();
+
+    assert_eq!(erc20.balance_of(RECEIVER).await, 2 * (forward_amount - fee));
+    assert_eq!(forwarder.get_accrued_fee(ft.id()).await.unwrap(), 2 * fee);
+
+    ft.storage_deposit(fees.id()).await.unwrap();
+    let result = fees
+        .call("claim_fees")
+        .args_json(near_sdk::serde_json::json!({
+            "forwarder_id": forwarder.id(),
+            "token_id": ft.id(),
+        }))
+        .max_gas()
+        .transact()
+        .await
+        .unwrap();
+    assert!(result.is_success());
+
+    assert_eq!(ft.ft_balance_of(fees.id()).await, 2 * fee);
+    assert_eq!(ft.ft_balance_of(forwarder.id()).await, 0);
+    assert_eq!(forwarder.get_accrued_fee(ft.id()).await.unwrap(), 0);
+}
+
+#[tokio::test]
+async fn test_forward_with_fee_above_max_ratio() {
+    use crate::sandbox::factory::Factory;
+
+    let forward_amount = 1_000_000_000;
+    let sandbox = Sandbox::new().await.unwrap();
+    let (ft, ft_owner) = sandbox.deploy_ft(TOTAL_SUPPLY, "USDT", 6).await.unwrap();
+    let aurora = sandbox.deploy_aurora("aurora").await.unwrap();
+    ft.storage_deposit(aurora.id()).await.unwrap();
+    let erc20 = aurora.deploy_erc20(ft.id()).await.unwrap();
+
+    // The fees contract charges 5%, which is above the maximum ratio of the forwarder.
+    let fees = sandbox.deploy_fees(&[ft.id()]).await.unwrap();
+    ft.storage_deposit(fees.id()).await.unwrap();
+
+    let factory = sandbox.deploy_factory(fees.id()).await.unwrap();
+    let forwarder_id = factory
+        .create(&[DeployParameters {
+            target_address: RECEIVER.to_string(),
+            target_network: aurora.id().as_str().parse().unwrap(),
+            wnear_contract_id: WNEAR.clone(),
+            skip_full_access_key: false,
+            max_fee_bps: Some(100),
+            target_account_id: None,
+        }])
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+
+    ft.storage_deposit(&forwarder_id).await.unwrap();
+    ft.ft_transfer(&ft_owner, &forwarder_id, forward_amount)
+        .await
+        .unwrap();
+
+    factory.forward(&forwarder_id, ft.id()).await.unwrap();
+
+    assert_eq!(erc20.balance_of(RECEIVER).await, 0);
+    assert_eq!(ft.ft_balance_of(fees.id()).await, 0);
+    assert_eq!(ft.ft_balance_of(&forwarder_id).await, forward_amount);
+}
+
+#[allow(clippy::similar_names)]
+#[tokio::test]
+async fn test_forward_many_tokens() {
+    use crate::sandbox::forwarder::Forwarder;
+
+    let forward_amount = 1_000_000_000;
+    let fee_percent = 5;
+    let sandbox = Sandbox::new().await.unwrap();
+    let (usdt, usdt_owner) = sandbox.deploy_ft(TOTAL_SUPPLY, "USDT", 6).await.unwrap();
+    let (usdc, usdc_owner) = sandbox.deploy_ft(TOTAL_SUPPLY, "USDC", 6).await.unwrap();
+
+    let aurora = sandbox.deploy_aurora("aurora").await.unwrap();
+    usdt.storage_deposit(aurora.id()).await.unwrap();
+    usdc.storage_deposit(aurora.id()).await.unwrap();
+
+    let usdt_erc20 = aurora.deploy_erc20(usdt.id()).await.unwrap();
+    let usdc_erc20 = aurora.deploy_erc20(usdc.id()).await.unwrap();
+
+    let fees = sandbox.deploy_fees(&[usdt.id(), usdc.id()]).await.unwrap();
+    usdt.storage_deposit(fees.id()).await.unwrap();
+    usdc.storage_deposit(fees.id()).await.unwrap();
+
+    let forwarder = sandbox
+        .deploy_forwarder(aurora.id(), RECEIVER, fees.id(), &WNEAR)
+        .await
+        .unwrap();
+    usdt.storage_deposit(forwarder.id()).await.unwrap();
+    usdc.storage_deposit(forwarder.id()).await.unwrap();
+
+    usdt.ft_transfer(&usdt_owner, forwarder.id(), forward_amount)
+        .await
+        .unwrap();
+    usdc.ft_transfer(&usdc_owner, forwarder.id(), forward_amount)
+        .await
+        .unwrap();
+
+    forwarder
+        .forward_many(&[usdt.id(), usdc.id()])
+        .await
+        .unwrap();
+
+    let fee = (forward_amount * fee_percent) / 100;
+    let balance = forward_amount - fee;
+
+    assert_eq!(usdt_erc20.balance_of(RECEIVER).await, balance);
+    assert_eq!(usdt.ft_balance_of(fees.id()).await, fee);
+    assert_eq!(usdt.ft_balance_of(forwarder.id()).await, 0);
+    assert_eq!(usdc_erc20.balance_of(RECEIVER).await, balance);
+    assert_eq!(usdc.ft_balance_of(fees.id()).await, fee);
+    assert_eq!(usdc.ft_balance_of(forwarder.id()).await, 0);
+}
+
+#[tokio::test]
+async fn test_split_between_targets() {
+    use crate::sandbox::forwarder::Forwarder;
+    use near_sdk::serde_json::json;
+
+    const COLD_RECEIVER: &str = "0x1111111111111111111111111111111111111111";
+
+    let forward_amount = 1_000_000_001;
+    let sandbox = Sandbox::new().await.unwrap();
+    let (usdt, usdt_owner) = sandbox.deploy_ft(TOTAL_SUPPLY, "USDT", 6).await.unwrap();
+    let aurora = sandbox.deploy_aurora("aurora").await.unwrap();
+    usdt.storage_deposit(aurora.id()).await.unwrap();
+    let erc20 = aurora.deploy_erc20(usdt.id()).await.unwrap();
+    let fees = sandbox.deploy_fees(&[usdt.id()]).await.unwrap();
+    usdt.storage_deposit(fees.id()).await.unwrap();
+    let forwarder = sandbox
+        .deploy_forwarder(aurora.id(), RECEIVER, fees.id(), &WNEAR)
+        .await
+        .unwrap();
+    usdt.storage_deposit(forwarder.id()).await.unwrap();
+
+    forwarder
+        .set_targets(&[(RECEIVER, 70), (COLD_RECEIVER, 30)])
+        .await
+        .unwrap();
+
+    assert_eq!(
+        forwarder.get_targets().await.unwrap(),
+        json!([
+            {"address": RECEIVER, "weight": 70},
+            {"address": COLD_RECEIVER, "weight": 30},
+        ])
+    );
+    assert!(forwarder.is_retargeted().await.unwrap());
+
+    usdt.ft_transfer(&usdt_owner, forwarder.id(), forward_amount)
+        .await
+        .unwrap();
+    forwarder.forward(usdt.id()).await.unwrap();
+
+    // The fee is 5% and the dust of the split goes to the first target.
+    let fee = forward_amount * 5 / 100;
+    let amount = forward_amount - fee;
+    let cold_amount = amount * 30 / 100;
+
+    assert_eq!(usdt.ft_balance_of(fees.id()).await, fee);
+    assert_eq!(erc20.balance_of(RECEIVER).await, amount - cold_amount);
+    assert_eq!(erc20.balance_of(COLD_RECEIVER).await, cold_amount);
+    assert_eq!(usdt.ft_balance_of(forwarder.id()).await, 0);
+}
+
+#[tokio::test]
+async fn test_split_between_targets_with_factory() {
+    use crate::sandbox::factory::Factory;
+    use near_sdk::serde_json::json;
+
+    // The forwarding to the maximum number of the targets fits the gas of the factory's call.
+    const TARGETS: [&str; 4] = [
+        RECEIVER,
+        "0x1111111111111111111111111111111111111111",
+        "0x2222222222222222222222222222222222222222",
+        "0x3333333333333333333333333333333333333333",
+    ];
+
+    let forward_amount = 1_000_000_000;
+    let sandbox = Sandbox::new().await.unwrap();
+    let (usdt, usdt_owner) = sandbox.deploy_ft(TOTAL_SUPPLY, "USDT", 6).await.unwrap();
+    let aurora = sandbox.deploy_aurora("aurora").await.unwrap();
+    usdt.storage_deposit(aurora.id()).await.unwrap();
+    let erc20 = aurora.deploy_erc20(usdt.id()).await.unwrap();
+    let fees = sandbox.deploy_fees(&[usdt.id()]).await.unwrap();
+    usdt.storage_deposit(fees.id()).await.unwrap();
+    let _ = sandbox.deploy_wrap_near().await.unwrap();
+    let factory = sandbox.deploy_factory(fees.id()).await.unwrap();
+    let parameters = DeployParameters {
+        target_address: RECEIVER.to_string(),
+        target_network: aurora.id().as_str().parse().unwrap(),
+        wnear_contract_id: WNEAR.clone(),
+        skip_full_access_key: false,
+        max_fee_bps: None,
+        target_account_id: None,
+    };
+    let forwarder_id = factory.create(&[parameters]).await.unwrap().remove(0);
+    usdt.storage_deposit(&forwarder_id).await.unwrap();
+
+    let result = factory
+        .call("set_forwarder_targets")
+        .args_json(json!({
+            "forwarder_id": forwarder_id,
+            "targets": TARGETS.map(|address| (address, 1)),
+        }))
+        .max_gas()
+        .transact()
+        .await
+        .unwrap();
+    assert!(result.is_success());
+
+    usdt.ft_transfer(&usdt_owner, &forwarder_id, forward_amount)
+        .await
+        .unwrap();
+    factory.forward(&forwarder_id, usdt.id()).await.unwrap();
+
+    // The fee is 5% and every target gets a quarter of the rest.
+    let fee = forward_amount * 5 / 100;
+    let share = (forward_amount - fee) / 4;
+
+    assert_eq!(usdt.ft_balance_of(fees.id()).await, fee);
+
+    for address in TARGETS {
+        assert_eq!(erc20.balance_of(address).await, share);
+    }
+
+    assert_eq!(usdt.ft_balance_of(&forwarder_id).await, 0);
+}
+
 #[tokio::test]
 async fn test_using_full_access_key() {
     let sandbox = Sandbox::new().await.unwrap();
@@ -203,6 +953,94 @@ async fn test_using_full_access_key() {
     assert!(matches!(key.permission, AccessKeyPermission::FullAccess));
 }
 
+#[tokio::test]
+async fn test_upgrade_forwarder_without_full_access_key() {
+    use crate::sandbox::factory::Factory;
+    use crate::sandbox::forwarder::Forwarder;
+
+    let sandbox = Sandbox::new().await.unwrap();
+    let pk = PublicKey::from_str("ed25519:BaiF3VUJf5pxB9ezVtzH4SejpdYc7EA3SqrKczsj1wno").unwrap();
+    let fees = sandbox.deploy_fees(&[]).await.unwrap();
+    let _ = sandbox.deploy_wrap_near().await.unwrap();
+    let factory = sandbox.deploy_factory(fees.id()).await.unwrap();
+    let parameters = DeployParameters {
+        target_address: RECEIVER.to_string(),
+        target_network: "silo.test.near".parse().unwrap(),
+        wnear_contract_id: WNEAR.as_str().parse().unwrap(),
+        skip_full_access_key: true,
+        max_fee_bps: None,
+        target_account_id: None,
+    };
+
+    let forwarder_ids = factory.create(&[parameters]).await.unwrap();
+    let forwarder_id = &forwarder_ids[0];
+
+    assert!(sandbox
+        .worker
+        .view_access_key(forwarder_id, &pk)
+        .await
+        .is_err());
+
+    factory.upgrade(forwarder_id).await.unwrap();
+
+    let forwarder = Contract::from_secret_key(
+        forwarder_id.clone(),
+        SecretKey::from_random(KeyType::ED25519),
+        &sandbox.worker,
+    );
+
+    assert_eq!(forwarder.get_target_address().await.unwrap(), RECEIVER);
+}
+
+#[tokio::test]
+async fn test_create_near_forwarder_with_factory() {
+    use crate::sandbox::factory::Factory;
+    use crate::sandbox::forwarder::Forwarder;
+    use near_sdk::serde_json::json;
+
+    let sandbox = Sandbox::new().await.unwrap();
+    let fees = sandbox.deploy_fees(&[]).await.unwrap();
+    let _ = sandbox.deploy_wrap_near().await.unwrap();
+    let factory = sandbox.deploy_factory(fees.id()).await.unwrap();
+    let parameters = DeployParameters {
+        target_address: RECEIVER.to_string(),
+        target_network: "silo.test.near".parse().unwrap(),
+        wnear_contract_id: WNEAR.as_str().parse().unwrap(),
+        skip_full_access_key: false,
+        max_fee_bps: None,
+        target_account_id: Some("exchange.test.near".parse().unwrap()),
+    };
+
+    let forwarder_ids = factory.create(&[parameters]).await.unwrap();
+    let forwarder = Contract::from_secret_key(
+        forwarder_ids[0].clone(),
+        SecretKey::from_random(KeyType::ED25519),
+        &sandbox.worker,
+    );
+
+    assert_eq!(
+        forwarder.get_target_account_id().await.unwrap(),
+        "exchange.test.near"
+    );
+
+    let result = factory
+        .call("set_forwarder_target_account")
+        .args_json(json!({
+            "forwarder_id": forwarder.id(),
+            "target_account_id": "other-exchange.test.near"
+        }))
+        .max_gas()
+        .transact()
+        .await
+        .unwrap();
+    assert!(result.is_success());
+
+    assert_eq!(
+        forwarder.get_target_account_id().await.unwrap(),
+        "other-exchange.test.near"
+    );
+}
+
 #[tokio::test]
 async fn test_using_factory() {
     use crate::sandbox::factory::Factory;
@@ -216,6 +1054,9 @@ async fn test_using_factory() {
             target_address: Address::from_array([u8::try_from(i).unwrap_or_default(); 20]).encode(),
             target_network: format!("silo-{i}.test.near").parse().unwrap(),
             wnear_contract_id: WNEAR.as_str().parse().unwrap(),
+            skip_full_access_key: false,
+            max_fee_bps: None,
+            target_account_id: None,
         })
         .collect::<Vec<_>>();
     let forwarder_ids = factory.create(&parameters).await.unwrap();
@@ -284,16 +1125,25 @@ async fn test_successful_complicated_flow() {
             target_address: alice_address.to_string(),
             target_network: silo1.id().as_str().parse().unwrap(),
             wnear_contract_id: WNEAR.clone(),
+            skip_full_access_key: false,
+            max_fee_bps: None,
+            target_account_id: None,
         },
         DeployParameters {
             target_address: bob_address.to_string(),
             target_network: silo2.id().as_str().parse().unwrap(),
             wnear_contract_id: WNEAR.clone(),
+            skip_full_access_key: false,
+            max_fee_bps: None,
+            target_account_id: None,
         },
         DeployParameters {
             target_address: john_address.to_string(),
             target_network: silo3.id().as_str().parse().unwrap(),
             wnear_contract_id: WNEAR.clone(),
+            skip_full_access_key: false,
+            max_fee_bps: None,
+            target_account_id: None,
         },
     ];
     let forward_ids: [_; 3] = factory
@@ -370,6 +1220,9 @@ async fn test_storage_deposit_refund() {
         target_address: Address::from_array([1; 20]).encode(),
         target_network: "silo.test.near".parse().unwrap(),
         wnear_contract_id: wnear.id().as_str().parse().unwrap(),
+        skip_full_access_key: false,
+        max_fee_bps: None,
+        target_account_id: None,
     };
 
     let forwarder_ids = factory.create(&[parameters]).await.unwrap();
@@ -380,8 +1233,8 @@ async fn test_storage_deposit_refund() {
 
     let balance_after_create = sandbox.balance(factory.id()).await;
     assert_eq!(
-        to_near(balance_before_create - balance_after_create),
-        0.31277 // Ⓝ
+        to_near(balance_before_create - balance_after_create - INIT_BALANCE.as_yoctonear()),
+        0.00277 // Ⓝ
     );
 
     factory.destroy(&forwarder_ids[0]).await.unwrap();
@@ -26,6 +26,9 @@ async fn test_forward_native_tokens() {
             target_address: super::RECEIVER.to_string(),
             target_network: silo.id().as_str().parse().unwrap(),
             wnear_contract_id: wrap.id().as_str().parse().unwrap(),
+            skip_full_access_key: false,
+            max_fee_bps: None,
+            target_account_id: None,
         }])
         .await
         .unwrap()
@@ -49,7 +52,10 @@ async fn test_forward_native_tokens() {
     factory.forward(&forwarder, &NEAR).await.unwrap();
 
     let fwd_balance = sandbox.balance(&forwarder).await;
-    assert_eq!(fwd_balance / rounder, INIT_BALANCE.as_millinear());
+    assert_eq!(
+        fwd_balance / rounder,
+        sandbox.native_reserve(&forwarder).await / rounder
+    );
 
     let fee = transfer.as_yoctonear() * 5 / 100;
     let deposit = transfer.as_yoctonear() - fee;
@@ -81,6 +87,9 @@ async fn test_forward_native_tokens_with_zero_fee() {
             target_address: super::RECEIVER.to_string(),
             target_network: silo.id().as_str().parse().unwrap(),
             wnear_contract_id: wrap.id().as_str().parse().unwrap(),
+            skip_full_access_key: false,
+            max_fee_bps: None,
+            target_account_id: None,
         }])
         .await
         .unwrap()
@@ -104,7 +113,10 @@ async fn test_forward_native_tokens_with_zero_fee() {
     factory.forward(&forwarder, &NEAR).await.unwrap();
 
     let fwd_balance = sandbox.balance(&forwarder).await;
-    assert_eq!(fwd_balance / rounder, INIT_BALANCE.as_millinear());
+    assert_eq!(
+        fwd_balance / rounder,
+        sandbox.native_reserve(&forwarder).await / rounder
+    );
 
     let deposit = transfer.as_yoctonear();
 